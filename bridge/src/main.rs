@@ -159,7 +159,7 @@ pub async fn ghostnet(
                 res @ Err(nom::Err::Incomplete(_)) => {
                     std::mem::drop(res);
 
-                    buf.extend(std::iter::repeat(0).take(start + 128 - buf.len()));
+                    buf.extend(std::iter::repeat_n(0, start + 128 - buf.len()));
                     eprintln!("reading");
                     let read = read.read(&mut buf[start..]).await?;
                     if read != 0 {
@@ -0,0 +1,23 @@
+use celeste::{binel::*, *};
+
+use std::env;
+use std::fs;
+
+fn main() -> Result<(), Error<'static>> {
+    let mut args = env::args().skip(1);
+    let (input, output) = match (args.next(), args.next()) {
+        (Some(input), Some(output)) => (input, output),
+        _ => {
+            eprintln!("usage: bin_to_xml <map.bin> <map.xml>");
+            return Ok(());
+        }
+    };
+
+    let map_bytes = fs::read(input)?;
+    let map_bin = parser::parse_file(&map_bytes)?;
+
+    let mut file = fs::File::create(output)?;
+    xml::write_file(&mut file, &map_bin)?;
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use celeste::{
+    binel::{serialize::*, *},
+    maps::render::*,
+    *,
+};
+
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+
+fn main() -> Result<(), Error<'static>> {
+    let mut args = env::args().skip(1);
+    let (input, output) = match (args.next(), args.next()) {
+        (Some(input), Some(output)) => (input, output),
+        _ => {
+            eprintln!("usage: render_map <map.bin> <map.png> [scale]");
+            return Ok(());
+        }
+    };
+    let scale = match args.next() {
+        Some(scale) => scale
+            .parse()
+            .map_err(|_| Error::io(ErrorKind::InvalidInput, "invalid scale"))?,
+        None => 1.0,
+    };
+
+    let map_bytes = fs::read(input)?;
+    let map_bin = parser::parse_file(&map_bytes)?;
+    let map = maps::Map::from_binel(BinElValue::Element(map_bin.root))?;
+
+    let options = RenderOptions {
+        scale,
+        ..RenderOptions::default()
+    };
+    let file = fs::File::create(output)?;
+    render(&map, &options)?.write_png(file)?;
+
+    Ok(())
+}
@@ -22,6 +22,7 @@ fn main() -> Result<(), Error<'static>> {
     let roundtrip_file = BinFile {
         root: roundtrip_bin,
         package: map_bin.package,
+        lookup: None,
     };
     let mut file = std::fs::File::create("roundtrip.bin")?;
     binel::writer::put_file(&mut file, &roundtrip_file)?;
@@ -7,6 +7,7 @@ use smallvec::*;
 use std::env;
 use std::error::Error;
 
+#[allow(clippy::unused_io_amount)] // reads one byte at a time until a frame parses
 async fn say_hi() -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
     let addr = env::args().nth(1).unwrap();
     let conn = TcpStream::connect(addr).await?;
@@ -0,0 +1,23 @@
+use celeste::{binel::*, *};
+
+use std::env;
+use std::fs;
+
+fn main() -> Result<(), Error<'static>> {
+    let mut args = env::args().skip(1);
+    let (input, output) = match (args.next(), args.next()) {
+        (Some(input), Some(output)) => (input, output),
+        _ => {
+            eprintln!("usage: xml_to_bin <map.xml> <map.bin>");
+            return Ok(());
+        }
+    };
+
+    let map_xml = fs::read_to_string(input)?;
+    let map_bin = xml::read_file(&map_xml)?;
+
+    let mut file = fs::File::create(output)?;
+    writer::put_file(&mut file, &map_bin)?;
+
+    Ok(())
+}
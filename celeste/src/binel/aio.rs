@@ -0,0 +1,207 @@
+use super::decode::{self, invalid, Payload};
+use super::*;
+use crate::Result;
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// Reads through `take`, so see `decode::check_len`.
+async fn read_bytes<R: AsyncRead + Unpin>(reader: &mut R, len: usize) -> Result<'static, Vec<u8>> {
+    let mut buf = Vec::new();
+    (&mut *reader)
+        .take(len as u64)
+        .read_to_end(&mut buf)
+        .await?;
+    decode::check_len(&buf, len)?;
+    Ok(buf)
+}
+
+async fn read_array<R: AsyncRead + Unpin, const N: usize>(
+    reader: &mut R,
+) -> Result<'static, [u8; N]> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
+async fn read_string<R: AsyncRead + Unpin>(reader: &mut R) -> Result<'static, String> {
+    let mut varint = decode::Varint::default();
+    let length = loop {
+        let [byte] = read_array(reader).await?;
+        if let Some(length) = varint.push(byte).map_err(invalid)? {
+            break length;
+        }
+    };
+
+    decode::read_utf8(read_bytes(reader, length).await?)
+}
+
+async fn read_lookup<'b, R: AsyncRead + Unpin>(
+    reader: &mut R,
+    lookup: &'b [String],
+) -> Result<'static, &'b String> {
+    decode::read_lookup(lookup, u16::from_le_bytes(read_array(reader).await?))
+}
+
+async fn read_attr<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    lookup: &[String],
+) -> Result<'static, BinElAttr> {
+    let [tag] = read_array(reader).await?;
+    let tag = decode::read_tag(tag)?;
+    Ok(match tag.payload() {
+        Payload::Number(len) => {
+            let mut buf = [0; 4];
+            reader.read_exact(&mut buf[..len]).await?;
+            tag.decode_number(&buf[..len])
+        }
+        Payload::Lookup => BinElAttr::Text(read_lookup(reader, lookup).await?.clone()),
+        Payload::String => BinElAttr::Text(read_string(reader).await?),
+        Payload::Rle => {
+            let length = i16::from_le_bytes(read_array(reader).await?);
+            let length = decode::read_rle_len(length)?;
+            BinElAttr::Text(decode::decode_rle(&read_bytes(reader, length).await?))
+        }
+    })
+}
+
+async fn read_element<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    lookup: &[String],
+) -> Result<'static, (BinEl, u16)> {
+    let mut binel = BinEl::new(read_lookup(reader, lookup).await?);
+
+    let [attr_count] = read_array(reader).await?;
+    for _ in 0..attr_count {
+        let key = read_lookup(reader, lookup).await?.clone();
+        let val = read_attr(reader, lookup).await?;
+        binel.attributes.insert(key, val);
+    }
+
+    let children = u16::from_le_bytes(read_array(reader).await?);
+    Ok((binel, children))
+}
+
+/// Read a `BinFile` from an `AsyncRead`. Only the bytes of the file are read,
+/// so more data may follow it. Wrapping the reader in a
+/// `futures::io::BufReader` is recommended, as many small reads are made.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::*;
+/// use futures::executor::block_on;
+/// use futures::io::Cursor;
+///
+/// let map_bytes = include_bytes!("../../tests/empty.bin");
+/// let bin = block_on(read_file(&mut Cursor::new(&map_bytes[..]))).unwrap();
+///
+/// assert_eq!(bin.root.name, "Map");
+/// ```
+pub async fn read_file<R: AsyncRead + Unpin>(reader: &mut R) -> Result<'static, BinFile> {
+    if read_string(reader).await? != "CELESTE MAP" {
+        return Err(invalid("Missing CELESTE MAP header"));
+    }
+
+    let package = read_string(reader).await?;
+
+    let length = i16::from_le_bytes(read_array(reader).await?);
+    let mut lookup = Vec::with_capacity(length.max(0) as usize);
+    for _ in 0..length {
+        lookup.push(read_string(reader).await?);
+    }
+
+    // elements that still have children to read, along with how many
+    let mut stack: Vec<(BinEl, u16)> = Vec::new();
+    loop {
+        let (mut done, children) = read_element(reader, &lookup).await?;
+        if children > 0 {
+            stack.push((done, children));
+            continue;
+        }
+
+        loop {
+            match stack.last_mut() {
+                None => {
+                    return Ok(BinFile {
+                        package,
+                        root: done,
+                        lookup: None,
+                    })
+                }
+                Some((parent, remaining)) => {
+                    parent.insert(done);
+                    *remaining -= 1;
+                    if *remaining > 0 {
+                        break;
+                    }
+                }
+            }
+            done = stack.pop().unwrap().0;
+        }
+    }
+}
+
+/// Write a `BinFile` to an `AsyncWrite`, the same way as `writer::put_file`.
+/// The file is encoded in memory before being written.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::*;
+/// use futures::executor::block_on;
+///
+/// let map_bytes = include_bytes!("../../tests/empty.bin");
+/// let bin = parser::parse_file(map_bytes).unwrap();
+///
+/// let mut buf = Vec::new();
+/// block_on(write_file(&mut buf, &bin)).unwrap();
+///
+/// assert_eq!(parser::parse_file(&buf).unwrap(), bin);
+/// ```
+pub async fn write_file<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    bin: &BinFile,
+) -> Result<'static, ()> {
+    let mut buf = Vec::new();
+    writer::put_file(&mut buf, bin)?;
+    writer.write_all(&buf).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::io::Cursor;
+
+    #[test]
+    fn read_consecutive_files() {
+        let map_bytes = include_bytes!("../../tests/empty.bin");
+        let bin = parser::parse_file(map_bytes).unwrap();
+
+        let mut buf = Vec::new();
+        block_on(write_file(&mut buf, &bin)).unwrap();
+        block_on(write_file(&mut buf, &bin)).unwrap();
+
+        let mut reader = Cursor::new(&buf[..]);
+        assert_eq!(block_on(read_file(&mut reader)).unwrap(), bin);
+        assert_eq!(block_on(read_file(&mut reader)).unwrap(), bin);
+        assert_eq!(reader.position() as usize, buf.len());
+    }
+
+    #[test]
+    fn read_truncated() {
+        let map_bytes = include_bytes!("../../tests/empty.bin");
+        let mut reader = Cursor::new(&map_bytes[..map_bytes.len() - 1]);
+        assert!(block_on(read_file(&mut reader)).is_err());
+    }
+
+    #[test]
+    fn reject_oversized_length() {
+        // a package claiming to be usize::MAX bytes long
+        let mut file = b"\x0bCELESTE MAP".to_vec();
+        file.extend_from_slice(&[0xff; 9]);
+        file.extend_from_slice(b"\x01pkg");
+
+        let err = block_on(read_file(&mut Cursor::new(&file[..]))).unwrap_err();
+        assert!(err.to_string().ends_with("but only 3 remain"));
+    }
+}
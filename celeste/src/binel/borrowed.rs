@@ -0,0 +1,140 @@
+use super::*;
+use std::borrow::Cow;
+
+/// A `BinFile` that borrows from the buffer it was parsed from. Parsed via
+/// `parser::take_file_ref`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct BinFileRef<'a> {
+    /// The file's "package". Usually the name of the file it is stored in.
+    pub package: &'a str,
+    /// The root element of the file.
+    pub root: BinElRef<'a>,
+}
+
+impl BinFileRef<'_> {
+    /// Convert into an owned `BinFile`.
+    pub fn into_owned(self) -> BinFile {
+        BinFile {
+            package: self.package.to_string(),
+            root: self.root.into_owned(),
+            lookup: None,
+        }
+    }
+}
+
+/// A `BinElAttr` that borrows from the buffer it was parsed from. Only strings
+/// stored in Celeste's RLE format need to be allocated.
+#[derive(Debug, PartialEq, Clone)]
+#[allow(missing_docs)]
+pub enum BinElAttrRef<'a> {
+    Bool(bool),
+    Int(i32),
+    Float(f32),
+    Text(Cow<'a, str>),
+}
+
+impl BinElAttrRef<'_> {
+    /// Convert into an owned `BinElAttr`.
+    pub fn into_owned(self) -> BinElAttr {
+        match self {
+            BinElAttrRef::Bool(val) => BinElAttr::Bool(val),
+            BinElAttrRef::Int(val) => BinElAttr::Int(val),
+            BinElAttrRef::Float(val) => BinElAttr::Float(val),
+            BinElAttrRef::Text(val) => BinElAttr::Text(val.into_owned()),
+        }
+    }
+}
+
+impl From<bool> for BinElAttrRef<'_> {
+    fn from(val: bool) -> Self {
+        BinElAttrRef::Bool(val)
+    }
+}
+
+impl From<i32> for BinElAttrRef<'_> {
+    fn from(val: i32) -> Self {
+        BinElAttrRef::Int(val)
+    }
+}
+
+impl From<f32> for BinElAttrRef<'_> {
+    fn from(val: f32) -> Self {
+        BinElAttrRef::Float(val)
+    }
+}
+
+impl From<BinElAttrRef<'_>> for BinElAttr {
+    fn from(attr: BinElAttrRef<'_>) -> Self {
+        attr.into_owned()
+    }
+}
+
+/// A `BinEl` that borrows its name, attribute names and strings from the
+/// buffer it was parsed from. Useful for quickly scanning many files.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct BinElRef<'a> {
+    /// The name of the `BinElRef`.
+    pub name: &'a str,
+    /// All attributes of the `BinElRef`, in document order.
+    pub attributes: IndexMap<&'a str, BinElAttrRef<'a>>,
+    children: Vec<BinElRef<'a>>,
+}
+
+impl<'a> BinElRef<'a> {
+    /// Create a new `BinElRef`.
+    #[inline]
+    pub fn new(name: &'a str) -> Self {
+        Self {
+            name,
+            attributes: IndexMap::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Get the text content of the `BinElRef`, if it exists.
+    #[inline]
+    pub fn text(&self) -> Option<&str> {
+        match self.attributes.get("innerText")? {
+            BinElAttrRef::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Add a child to the end of the `BinElRef`.
+    #[inline]
+    pub fn insert(&mut self, child: Self) {
+        self.children.push(child);
+    }
+
+    /// Get all children of the `BinElRef`, in document order.
+    #[inline]
+    pub fn children<'b>(&'b self) -> impl Iterator<Item = &'b Self> + 'b {
+        self.children.iter()
+    }
+
+    /// Get children of the `BinElRef` by name, in document order.
+    #[inline]
+    pub fn get<'b>(&'b self, name: &'b str) -> impl Iterator<Item = &'b Self> + 'b {
+        self.children().filter(move |child| child.name == name)
+    }
+
+    /// Convert into an owned `BinEl`.
+    pub fn into_owned(self) -> BinEl {
+        let mut binel = BinEl::new(self.name);
+        binel.attributes = self
+            .attributes
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.into_owned()))
+            .collect();
+        for child in self.children {
+            binel.insert(child.into_owned());
+        }
+        binel
+    }
+}
+
+impl From<BinElRef<'_>> for BinEl {
+    fn from(binel: BinElRef<'_>) -> Self {
+        binel.into_owned()
+    }
+}
@@ -0,0 +1,206 @@
+use super::*;
+use crate::{Error, Result};
+use serialize::BinElValue;
+
+macro_rules! impl_from_primitive {
+    ($attr:ident, $type:ty, $val:ty) => {
+        impl From<$type> for BinElAttr {
+            fn from(val: $type) -> Self {
+                BinElAttr::$attr(<$val>::from(val))
+            }
+        }
+    };
+}
+
+impl_from_primitive!(Bool, bool, bool);
+impl_from_primitive!(Int, u8, i32);
+impl_from_primitive!(Int, u16, i32);
+impl_from_primitive!(Int, i8, i32);
+impl_from_primitive!(Int, i16, i32);
+impl_from_primitive!(Int, i32, i32);
+impl_from_primitive!(Float, f32, f32);
+impl_from_primitive!(Text, String, String);
+impl_from_primitive!(Text, &str, String);
+
+/// Builds a `BinEl`, created with `BinEl::build`.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::*;
+///
+/// let level = BinEl::build("level")
+///     .attr("name", "a-00")
+///     .attr("x", 0)
+///     .attr("dark", true)
+///     .child(BinEl::build("solids").text("0110"))
+///     .child(BinEl::build("entities").children((0..3).map(|id| {
+///         BinEl::build("spikesUp").attr("id", id).attr("x", id * 8)
+///     })))
+///     .finish();
+///
+/// assert_eq!(level.attr_str("name").unwrap(), "a-00");
+/// assert!(level.attr_bool("dark").unwrap());
+/// assert_eq!(level.get("entities").next().unwrap().children().count(), 3);
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct BinElBuilder {
+    elem: BinEl,
+}
+
+impl BinElBuilder {
+    /// Set an attribute, replacing it if it already exists.
+    pub fn attr(mut self, key: &str, value: impl Into<BinElAttr>) -> Self {
+        self.elem.attributes.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Set the text content.
+    pub fn text(mut self, text: &str) -> Self {
+        self.elem.set_text(text);
+        self
+    }
+
+    /// Add a child to the end.
+    pub fn child(mut self, child: impl Into<BinEl>) -> Self {
+        self.elem.insert(child.into());
+        self
+    }
+
+    /// Add children to the end.
+    pub fn children<T: Into<BinEl>>(mut self, children: impl IntoIterator<Item = T>) -> Self {
+        for child in children {
+            self.elem.insert(child.into());
+        }
+        self
+    }
+
+    /// Finish building the `BinEl`.
+    pub fn finish(self) -> BinEl {
+        self.elem
+    }
+}
+
+impl From<BinElBuilder> for BinEl {
+    fn from(builder: BinElBuilder) -> Self {
+        builder.finish()
+    }
+}
+
+fn attr_error(elem: &BinEl, key: &str, expected: &'static str) -> Error<'static> {
+    let received = match elem.attributes.get(key) {
+        Some(attr) => BinElValue::Attribute(attr.clone()).describe(),
+        None => BinElValue::None.describe(),
+    };
+    Error::deserialize(expected, received)
+        .in_path(key)
+        .in_path(&elem.name)
+}
+
+impl BinEl {
+    /// Start building a `BinEl`.
+    #[inline]
+    pub fn build(name: &str) -> BinElBuilder {
+        BinElBuilder {
+            elem: BinEl::new(name),
+        }
+    }
+
+    /// Get an int attribute. Returns `Error::Deserialize` if it is missing or
+    /// has a different type.
+    ///
+    /// # Examples:
+    /// ```
+    /// use celeste::binel::*;
+    ///
+    /// let level = BinEl::build("level").attr("x", 8).attr("name", "a-00").finish();
+    ///
+    /// assert_eq!(level.attr_i32("x").unwrap(), 8);
+    /// assert_eq!(
+    ///     level.attr_i32("name").unwrap_err().to_string(),
+    ///     "Expected int attribute but received text attribute in `level/name`"
+    /// );
+    /// ```
+    pub fn attr_i32(&self, key: &str) -> Result<'static, i32> {
+        match self.attributes.get(key) {
+            Some(BinElAttr::Int(val)) => Ok(*val),
+            _ => Err(attr_error(self, key, "int attribute")),
+        }
+    }
+
+    /// Get a float attribute, also accepting ints, as Celeste does. Returns
+    /// `Error::Deserialize` if it is missing or has a different type.
+    pub fn attr_f32(&self, key: &str) -> Result<'static, f32> {
+        match self.attributes.get(key) {
+            Some(BinElAttr::Float(val)) => Ok(*val),
+            Some(BinElAttr::Int(val)) => Ok(*val as f32),
+            _ => Err(attr_error(self, key, "float attribute")),
+        }
+    }
+
+    /// Get a bool attribute. Returns `Error::Deserialize` if it is missing or
+    /// has a different type.
+    pub fn attr_bool(&self, key: &str) -> Result<'static, bool> {
+        match self.attributes.get(key) {
+            Some(BinElAttr::Bool(val)) => Ok(*val),
+            _ => Err(attr_error(self, key, "bool attribute")),
+        }
+    }
+
+    /// Get a text attribute. Returns `Error::Deserialize` if it is missing or
+    /// has a different type.
+    pub fn attr_str(&self, key: &str) -> Result<'static, &str> {
+        match self.attributes.get(key) {
+            Some(BinElAttr::Text(val)) => Ok(val),
+            _ => Err(attr_error(self, key, "text attribute")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn build_matches_manual() {
+        let mut manual = BinEl::new("level");
+        manual
+            .attributes
+            .insert("x".to_string(), BinElAttr::Int(-8));
+        manual
+            .attributes
+            .insert("dark".to_string(), BinElAttr::Bool(true));
+        manual
+            .attributes
+            .insert("scale".to_string(), BinElAttr::Float(0.5));
+        manual.insert(BinEl::new("entities"));
+
+        let built = BinEl::build("level")
+            .attr("x", -8i16)
+            .attr("dark", true)
+            .attr("scale", 0.5)
+            .child(BinEl::new("entities"))
+            .finish();
+        assert_eq!(built, manual);
+    }
+
+    #[test]
+    fn typed_getters() {
+        let level = BinEl::build("level")
+            .attr("x", 8)
+            .attr("alpha", 0.5)
+            .attr("dark", false)
+            .finish();
+
+        assert_eq!(level.attr_f32("x").unwrap(), 8.0);
+        assert_eq!(level.attr_f32("alpha").unwrap(), 0.5);
+        assert!(!level.attr_bool("dark").unwrap());
+        assert_eq!(
+            level.attr_str("missing").unwrap_err().to_string(),
+            "Expected text attribute but received nothing in `level/missing`"
+        );
+        assert_eq!(
+            level.attr_bool("x").unwrap_err().to_string(),
+            "Expected bool attribute but received int attribute in `level/x`"
+        );
+    }
+}
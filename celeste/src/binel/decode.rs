@@ -0,0 +1,159 @@
+//! Decoding shared by every reader of `BinaryElement` files: `parser` for both
+//! owned and borrowed trees, `stream`, and the async reader. Each reader only
+//! supplies its own I/O.
+
+use super::*;
+use crate::Error;
+use std::convert::TryInto;
+use std::io::ErrorKind;
+
+/// What follows an attribute's tag.
+pub(crate) enum Payload {
+    /// A bool, int or float stored in this many bytes. Decoded with
+    /// `BinElAttrTag::decode_number`.
+    Number(usize),
+    /// A u16 index into the lookup table.
+    Lookup,
+    /// A string with a varint length.
+    String,
+    /// A string in Celeste's RLE format, with an i16 length. The length is
+    /// checked with `rle_len`, and the runs decoded with `decode_rle`.
+    Rle,
+}
+
+impl BinElAttrTag {
+    pub(crate) fn payload(self) -> Payload {
+        use BinElAttrTag::*;
+        match self {
+            Bool | U8 => Payload::Number(1),
+            I16 => Payload::Number(2),
+            I32 | Float => Payload::Number(4),
+            Lookup => Payload::Lookup,
+            String => Payload::String,
+            Rle => Payload::Rle,
+        }
+    }
+
+    /// Decode a number from exactly as many bytes as its `Payload::Number`.
+    pub(crate) fn decode_number<T>(self, bytes: &[u8]) -> T
+    where
+        T: From<bool> + From<i32> + From<f32>,
+    {
+        use BinElAttrTag::*;
+        match self {
+            Bool => T::from(bytes[0] != 0),
+            U8 => T::from(i32::from(bytes[0])),
+            I16 => T::from(i32::from(i16::from_le_bytes(bytes.try_into().unwrap()))),
+            I32 => T::from(i32::from_le_bytes(bytes.try_into().unwrap())),
+            Float => T::from(f32::from_le_bytes(bytes.try_into().unwrap())),
+            Lookup | String | Rle => unreachable!("{:?} isn't a number", self),
+        }
+    }
+}
+
+/// The number of bytes in an RLE string, if its length is even and not
+/// negative.
+pub(crate) fn rle_len(len: i16) -> Option<usize> {
+    if len >= 0 && len % 2 == 0 {
+        Some(len as usize)
+    } else {
+        None
+    }
+}
+
+/// Decode the runs of an RLE string. Each run is a count followed by a single
+/// Latin-1 byte, which maps directly to a `char`.
+pub(crate) fn decode_rle(bytes: &[u8]) -> String {
+    bytes
+        .chunks_exact(2)
+        .flat_map(|run| std::iter::repeat_n(run[1] as char, run[0] as usize))
+        .collect()
+}
+
+/// Decodes a varint one byte at a time, so readers that can't look ahead don't
+/// need to buffer it.
+#[derive(Default)]
+pub(crate) struct Varint {
+    value: usize,
+    shift: u32,
+}
+
+impl Varint {
+    /// Add the next byte, returning the value once it is complete.
+    pub(crate) fn push(&mut self, byte: u8) -> std::result::Result<Option<usize>, &'static str> {
+        let bits = usize::from(byte & 0x7f);
+        if self.shift >= usize::BITS || (bits << self.shift) >> self.shift != bits {
+            return Err("Varint is too large");
+        }
+
+        self.value |= bits << self.shift;
+        self.shift += 7;
+        Ok(if byte & 0x80 == 0 {
+            Some(self.value)
+        } else {
+            None
+        })
+    }
+}
+
+// The rest is for readers that report errors as `Error::Io`.
+
+pub(crate) fn invalid(text: impl Into<String>) -> Error<'static> {
+    Error::io(ErrorKind::InvalidData, text.into())
+}
+
+pub(crate) fn read_tag(byte: u8) -> Result<'static, BinElAttrTag> {
+    BinElAttrTag::from_u8(byte)
+        .ok_or_else(|| invalid(format!("Unknown attribute tag {:#04x}", byte)))
+}
+
+pub(crate) fn read_rle_len(len: i16) -> Result<'static, usize> {
+    rle_len(len).ok_or_else(|| invalid(format!("Invalid RLE string length {}", len)))
+}
+
+pub(crate) fn read_lookup(lookup: &[String], index: u16) -> Result<'static, &String> {
+    lookup
+        .get(index as usize)
+        .ok_or_else(|| invalid(format!("Lookup index {} is out of range", index)))
+}
+
+/// Check that a read through `take` got every byte it asked for. Reading that
+/// way means a corrupt length can only allocate as much as the input holds.
+pub(crate) fn check_len(buf: &[u8], len: usize) -> Result<'static, ()> {
+    if buf.len() < len {
+        return Err(Error::io(
+            ErrorKind::UnexpectedEof,
+            format!("Expected {} bytes, but only {} remain", len, buf.len()),
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn read_utf8(buf: Vec<u8>) -> Result<'static, String> {
+    String::from_utf8(buf).map_err(|err| invalid(err.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn varint_limits() {
+        let mut varint = Varint::default();
+        assert_eq!(varint.push(0xac), Ok(None));
+        assert_eq!(varint.push(0x02), Ok(Some(300)));
+
+        let mut varint = Varint::default();
+        for _ in 0..9 {
+            assert_eq!(varint.push(0xff), Ok(None));
+        }
+        assert!(varint.push(0x02).is_err());
+    }
+
+    #[test]
+    fn rle_runs() {
+        assert_eq!(rle_len(-2), None);
+        assert_eq!(rle_len(3), None);
+        assert_eq!(decode_rle(b"\x03\xe9\x01a"), "éééa");
+    }
+}
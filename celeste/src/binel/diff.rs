@@ -0,0 +1,638 @@
+use super::*;
+use crate::{Error, Result};
+use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Attributes that identify an element among its siblings, in order of
+/// preference. Rooms are matched by `name`, and entities and triggers by `id`.
+pub const KEY_ATTRS: &[&str] = &["id", "name"];
+
+/// How an element is matched with an element in another tree.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Key {
+    /// The element has one of `KEY_ATTRS`, with a value unique among its
+    /// siblings of the same name.
+    Attr {
+        /// The name of the attribute.
+        attr: String,
+        /// The value of the attribute.
+        value: BinElAttr,
+    },
+    /// The element is the nth of its siblings of the same name without a
+    /// `Key::Attr`.
+    Index(usize),
+}
+
+/// A single step in the path to an element.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Selector {
+    /// The name of the element.
+    pub name: String,
+    /// How the element is matched among its siblings.
+    pub key: Key,
+}
+
+/// A change between two `BinEl` trees. Paths are relative to the root, which
+/// has an empty path.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[allow(missing_docs)]
+pub enum Change {
+    /// An element was added after all of its siblings.
+    ElementAdded { path: Vec<Selector>, element: BinEl },
+    /// An element was removed.
+    ElementRemoved { path: Vec<Selector>, element: BinEl },
+    /// An attribute was added.
+    AttributeAdded {
+        path: Vec<Selector>,
+        attr: String,
+        value: BinElAttr,
+    },
+    /// An attribute was removed.
+    AttributeRemoved {
+        path: Vec<Selector>,
+        attr: String,
+        value: BinElAttr,
+    },
+    /// An attribute's value changed.
+    AttributeChanged {
+        path: Vec<Selector>,
+        attr: String,
+        old: BinElAttr,
+        new: BinElAttr,
+    },
+}
+
+/// A semantic diff between two `BinEl` trees, created by `diff`. It can be
+/// applied to a third tree with `Diff::apply`, and printed for review.
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Diff {
+    /// All changes, in document order.
+    pub changes: Vec<Change>,
+}
+
+fn key_id(name: &str, attr: &str, value: &BinElAttr) -> String {
+    format!("{}\0{}\0{:?}", name, attr, value)
+}
+
+// The selectors of all children of `parent`, in document order.
+fn selectors(parent: &BinEl) -> Vec<Selector> {
+    let mut used = HashSet::new();
+    let mut indices: HashMap<&str, usize> = HashMap::new();
+
+    parent
+        .children()
+        .map(|child| {
+            let attr_key = KEY_ATTRS.iter().find_map(|&attr| {
+                let value = child.attributes.get(attr)?;
+                if used.insert(key_id(&child.name, attr, value)) {
+                    Some(Key::Attr {
+                        attr: attr.to_string(),
+                        value: value.clone(),
+                    })
+                } else {
+                    None
+                }
+            });
+
+            let key = attr_key.unwrap_or_else(|| {
+                let index = indices.entry(&child.name).or_insert(0);
+                *index += 1;
+                Key::Index(*index - 1)
+            });
+
+            Selector {
+                name: child.name.clone(),
+                key,
+            }
+        })
+        .collect()
+}
+
+fn diff_element(old: &BinEl, new: &BinEl, path: &mut Vec<Selector>, changes: &mut Vec<Change>) {
+    for (attr, old_value) in &old.attributes {
+        match new.attributes.get(attr) {
+            Some(new_value) if new_value == old_value => {}
+            Some(new_value) => changes.push(Change::AttributeChanged {
+                path: path.clone(),
+                attr: attr.clone(),
+                old: old_value.clone(),
+                new: new_value.clone(),
+            }),
+            None => changes.push(Change::AttributeRemoved {
+                path: path.clone(),
+                attr: attr.clone(),
+                value: old_value.clone(),
+            }),
+        }
+    }
+
+    for (attr, value) in &new.attributes {
+        if !old.attributes.contains_key(attr) {
+            changes.push(Change::AttributeAdded {
+                path: path.clone(),
+                attr: attr.clone(),
+                value: value.clone(),
+            });
+        }
+    }
+
+    let old_selectors = selectors(old);
+    let new_selectors = selectors(new);
+
+    for (selector, old_child) in old_selectors.iter().zip(old.children()) {
+        path.push(selector.clone());
+        match new_selectors.iter().position(|e| e == selector) {
+            Some(i) => diff_element(old_child, &new.children[i], path, changes),
+            None => changes.push(Change::ElementRemoved {
+                path: path.clone(),
+                element: old_child.clone(),
+            }),
+        }
+        path.pop();
+    }
+
+    for (selector, new_child) in new_selectors.iter().zip(new.children()) {
+        if !old_selectors.contains(selector) {
+            path.push(selector.clone());
+            changes.push(Change::ElementAdded {
+                path: path.clone(),
+                element: new_child.clone(),
+            });
+            path.pop();
+        }
+    }
+}
+
+/// Compute a semantic diff between two `BinEl` trees. Children are matched by
+/// their name and the first of `KEY_ATTRS` they have, or otherwise by their
+/// position among siblings of the same name. The roots are compared regardless
+/// of their names.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::{diff::*, *};
+///
+/// let mut old = BinEl::new("levels");
+/// let mut level = BinEl::new("level");
+/// level.attributes.insert("name".to_string(), BinElAttr::Text("a-00".to_string()));
+/// level.attributes.insert("width".to_string(), BinElAttr::Int(320));
+/// old.insert(level);
+///
+/// let mut new = old.clone();
+/// for level in new.children_mut() {
+///     level.attributes.insert("width".to_string(), BinElAttr::Int(328));
+/// }
+///
+/// let diff = diff(&old, &new);
+/// assert_eq!(diff.to_string(), "~ level[@name='a-00']@width: 320 -> 328\n");
+/// ```
+pub fn diff(old: &BinEl, new: &BinEl) -> Diff {
+    let mut changes = Vec::new();
+    diff_element(old, new, &mut Vec::new(), &mut changes);
+    Diff { changes }
+}
+
+fn display_path(path: &[Selector]) -> String {
+    if path.is_empty() {
+        ".".to_string()
+    } else {
+        path.iter().map(ToString::to_string).join("/")
+    }
+}
+
+fn conflict(path: &[Selector], message: impl Into<String>) -> Error<'static> {
+    Error::Patch {
+        path: display_path(path),
+        message: message.into(),
+    }
+}
+
+fn at<'a>(root: &'a BinEl, positions: &[usize]) -> &'a BinEl {
+    positions.iter().fold(root, |elem, &i| &elem.children[i])
+}
+
+fn at_mut<'a>(root: &'a mut BinEl, positions: &[usize]) -> &'a mut BinEl {
+    positions
+        .iter()
+        .fold(root, |elem, &i| &mut elem.children[i])
+}
+
+// Resolves paths against the tree a diff is applied to, as it was before any
+// change was made, so index keys keep matching the elements they were
+// computed from. The selectors of each parent are only computed once.
+struct Resolver<'a> {
+    root: &'a BinEl,
+    selectors: HashMap<Vec<usize>, Vec<Selector>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(root: &'a BinEl) -> Self {
+        Self {
+            root,
+            selectors: HashMap::new(),
+        }
+    }
+
+    fn position(&mut self, parent: &[usize], selector: &Selector) -> Option<usize> {
+        let root = self.root;
+        self.selectors
+            .entry(parent.to_vec())
+            .or_insert_with(|| selectors(at(root, parent)))
+            .iter()
+            .position(|e| e == selector)
+    }
+
+    fn resolve(&mut self, path: &[Selector]) -> Result<'static, Vec<usize>> {
+        let mut positions = Vec::with_capacity(path.len());
+        for (depth, selector) in path.iter().enumerate() {
+            match self.position(&positions, selector) {
+                Some(position) => positions.push(position),
+                None => return Err(conflict(&path[..=depth], "Element is missing")),
+            }
+        }
+        Ok(positions)
+    }
+}
+
+impl Change {
+    // Removed elements are collected in `removed`, and only removed once every
+    // other change has been made.
+    fn apply(
+        &self,
+        resolver: &mut Resolver,
+        patched: &mut BinEl,
+        removed: &mut Vec<Vec<usize>>,
+    ) -> Result<'static, ()> {
+        match self {
+            Change::ElementAdded { path, element } => {
+                let (selector, parent) = match path.split_last() {
+                    Some(split) => split,
+                    None => return Err(conflict(path, "The root can't be added")),
+                };
+                let parent = resolver.resolve(parent)?;
+
+                if let Some(index) = resolver.position(&parent, selector) {
+                    if at(resolver.root, &parent).children[index] == *element {
+                        return Ok(());
+                    }
+                    // unkeyed elements can't be told apart from existing ones
+                    if let Key::Attr { .. } = selector.key {
+                        return Err(conflict(path, "A different element already exists"));
+                    }
+                }
+
+                at_mut(patched, &parent).insert(element.clone());
+            }
+            Change::ElementRemoved { path, element } => {
+                if path.is_empty() {
+                    return Err(conflict(path, "The root can't be removed"));
+                }
+                let positions = resolver.resolve(path)?;
+                if at(resolver.root, &positions) != element {
+                    return Err(conflict(path, "Removed element was changed"));
+                }
+                removed.push(positions);
+            }
+            Change::AttributeAdded { path, attr, value } => {
+                let elem = at_mut(patched, &resolver.resolve(path)?);
+                match elem.attributes.get(attr) {
+                    Some(existing) if existing != value => {
+                        return Err(conflict(path, format!("Attribute {} already exists", attr)))
+                    }
+                    _ => {
+                        elem.attributes.insert(attr.clone(), value.clone());
+                    }
+                }
+            }
+            Change::AttributeRemoved { path, attr, value } => {
+                let elem = at_mut(patched, &resolver.resolve(path)?);
+                match elem.attributes.get(attr) {
+                    Some(existing) if existing != value => {
+                        return Err(conflict(path, format!("Attribute {} was changed", attr)))
+                    }
+                    _ => {
+                        elem.attributes.shift_remove(attr);
+                        elem.tags.shift_remove(attr);
+                    }
+                }
+            }
+            Change::AttributeChanged {
+                path,
+                attr,
+                old,
+                new,
+            } => {
+                let elem = at_mut(patched, &resolver.resolve(path)?);
+                match elem.attributes.get_mut(attr) {
+                    Some(existing) if existing == old || existing == new => *existing = new.clone(),
+                    Some(_) => {
+                        return Err(conflict(path, format!("Attribute {} was changed", attr)))
+                    }
+                    None => return Err(conflict(path, format!("Attribute {} is missing", attr))),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Diff {
+    /// Whether there are no changes.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Apply the changes to `target`, which may be a different tree than the
+    /// diff was computed from. Paths are matched against `target` as it was
+    /// before any change was made. Additions and attribute changes that were
+    /// already made are skipped, so a diff without removals can be applied
+    /// again. Returns `Error::Patch` if a change conflicts with `target`,
+    /// including when an element to remove is missing, in which case `target`
+    /// is left unchanged.
+    ///
+    /// # Examples:
+    /// ```
+    /// use celeste::binel::{diff::*, *};
+    ///
+    /// let mut old = BinEl::new("entities");
+    /// let mut spikes = BinEl::new("spikesUp");
+    /// spikes.attributes.insert("id".to_string(), BinElAttr::Int(1));
+    /// old.insert(spikes);
+    ///
+    /// let mut new = old.clone();
+    /// new.drain().for_each(drop);
+    ///
+    /// let mut other = old.clone();
+    /// other.insert(BinEl::new("player"));
+    ///
+    /// diff(&old, &new).apply(&mut other).unwrap();
+    /// assert_eq!(other.children().map(|e| &e.name).collect::<Vec<_>>(), vec!["player"]);
+    /// ```
+    pub fn apply(&self, target: &mut BinEl) -> Result<'static, ()> {
+        let mut patched = target.clone();
+        let mut resolver = Resolver::new(target);
+        let mut removed = Vec::new();
+        for change in &self.changes {
+            change.apply(&mut resolver, &mut patched, &mut removed)?;
+        }
+
+        // removing from the back keeps the positions of the rest valid
+        removed.sort_unstable_by(|a, b| b.cmp(a));
+        for positions in removed {
+            let (index, parent) = positions.split_last().unwrap();
+            at_mut(&mut patched, parent).children.remove(*index);
+        }
+
+        *target = patched;
+        Ok(())
+    }
+}
+
+fn display_attr(value: &BinElAttr) -> String {
+    match value {
+        BinElAttr::Bool(val) => val.to_string(),
+        BinElAttr::Int(val) => val.to_string(),
+        BinElAttr::Float(val) => format!("{:?}", val),
+        BinElAttr::Text(val) => format!("'{}'", val),
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.key {
+            Key::Attr { attr, value } => {
+                write!(f, "{}[@{}={}]", self.name, attr, display_attr(value))
+            }
+            Key::Index(index) => write!(f, "{}", path_segment(&self.name, *index)),
+        }
+    }
+}
+
+/// One line per change, marked with `+` for additions, `-` for removals and `~`
+/// for changes. Paths are written in the syntax of `query::Query`, with
+/// attributes following an `@`.
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.changes {
+            match change {
+                Change::ElementAdded { path, .. } => writeln!(f, "+ {}", display_path(path))?,
+                Change::ElementRemoved { path, .. } => writeln!(f, "- {}", display_path(path))?,
+                Change::AttributeAdded { path, attr, value } => writeln!(
+                    f,
+                    "+ {}@{}: {}",
+                    display_path(path),
+                    attr,
+                    display_attr(value)
+                )?,
+                Change::AttributeRemoved { path, attr, value } => writeln!(
+                    f,
+                    "- {}@{}: {}",
+                    display_path(path),
+                    attr,
+                    display_attr(value)
+                )?,
+                Change::AttributeChanged {
+                    path,
+                    attr,
+                    old,
+                    new,
+                } => writeln!(
+                    f,
+                    "~ {}@{}: {} -> {}",
+                    display_path(path),
+                    attr,
+                    display_attr(old),
+                    display_attr(new)
+                )?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entity(name: &str, id: i32, x: i32) -> BinEl {
+        let mut entity = BinEl::new(name);
+        entity
+            .attributes
+            .insert("id".to_string(), BinElAttr::Int(id));
+        entity.attributes.insert("x".to_string(), BinElAttr::Int(x));
+        entity
+    }
+
+    fn level(name: &str, entities: Vec<BinEl>) -> BinEl {
+        let mut level = BinEl::new("level");
+        level
+            .attributes
+            .insert("name".to_string(), BinElAttr::Text(name.to_string()));
+        let mut entities_elem = BinEl::new("entities");
+        for entity in entities {
+            entities_elem.insert(entity);
+        }
+        level.insert(entities_elem);
+        level
+    }
+
+    fn map(levels: Vec<BinEl>) -> BinEl {
+        let mut levels_elem = BinEl::new("levels");
+        for level in levels {
+            levels_elem.insert(level);
+        }
+        let mut map = BinEl::new("Map");
+        map.insert(levels_elem);
+        map
+    }
+
+    #[test]
+    fn diff_matches_by_key() {
+        let old = map(vec![
+            level(
+                "a-00",
+                vec![entity("player", 0, 8), entity("spikesUp", 1, 16)],
+            ),
+            level("a-01", vec![]),
+        ]);
+        let mut new = map(vec![
+            level("a-01", vec![entity("refill", 5, 0)]),
+            level(
+                "a-00",
+                vec![entity("spikesUp", 1, 24), entity("player", 0, 8)],
+            ),
+        ]);
+        new.attributes
+            .insert("dark".to_string(), BinElAttr::Bool(true));
+
+        assert_eq!(
+            diff(&old, &new).to_string(),
+            "+ .@dark: true\n\
+             ~ levels/level[@name='a-00']/entities/spikesUp[@id=1]@x: 16 -> 24\n\
+             + levels/level[@name='a-01']/entities/refill[@id=5]\n"
+        );
+        assert!(diff(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn apply_to_other_tree() {
+        let old = map(vec![level("a-00", vec![entity("spikesUp", 1, 16)])]);
+        let new = map(vec![level(
+            "a-00",
+            vec![entity("spikesUp", 1, 24), entity("refill", 2, 0)],
+        )]);
+        let patch = diff(&old, &new);
+
+        let mut other = map(vec![
+            level(
+                "a-00",
+                vec![entity("spikesUp", 1, 16), entity("player", 3, 0)],
+            ),
+            level("b-00", vec![]),
+        ]);
+        patch.apply(&mut other).unwrap();
+        assert_eq!(
+            other,
+            map(vec![
+                level(
+                    "a-00",
+                    vec![
+                        entity("spikesUp", 1, 24),
+                        entity("player", 3, 0),
+                        entity("refill", 2, 0)
+                    ]
+                ),
+                level("b-00", vec![]),
+            ])
+        );
+
+        // applying again changes nothing
+        let patched = other.clone();
+        patch.apply(&mut other).unwrap();
+        assert_eq!(other, patched);
+    }
+
+    #[test]
+    fn apply_conflict() {
+        let old = map(vec![level("a-00", vec![entity("spikesUp", 1, 16)])]);
+        let new = map(vec![level("a-00", vec![entity("spikesUp", 1, 24)])]);
+
+        let mut other = map(vec![level("a-00", vec![entity("spikesUp", 1, 32)])]);
+        let unchanged = other.clone();
+        match diff(&old, &new).apply(&mut other) {
+            Err(Error::Patch { path, .. }) => {
+                assert_eq!(path, "levels/level[@name='a-00']/entities/spikesUp[@id=1]")
+            }
+            other => panic!("expected Patch, got {:?}", other),
+        }
+        assert_eq!(other, unchanged);
+
+        let mut other = map(vec![]);
+        assert!(diff(&old, &new).apply(&mut other).is_err());
+    }
+
+    fn decals(textures: &[&str]) -> BinEl {
+        BinEl::build("fgdecals")
+            .children(
+                textures
+                    .iter()
+                    .map(|&texture| BinEl::build("decal").attr("texture", texture)),
+            )
+            .finish()
+    }
+
+    #[test]
+    fn apply_unkeyed_removals() {
+        let old = decals(&["a", "b", "c"]);
+        let new = decals(&["c"]);
+        let patch = diff(&old, &new);
+        assert_eq!(
+            patch.to_string(),
+            "~ decal@texture: 'a' -> 'c'\n- decal[1]\n- decal[2]\n"
+        );
+
+        let mut patched = old.clone();
+        patch.apply(&mut patched).unwrap();
+        assert_eq!(patched, new);
+
+        // the removals were already made
+        let mut other = decals(&["a", "b"]);
+        match patch.apply(&mut other) {
+            Err(Error::Patch { path, .. }) => assert_eq!(path, "decal[2]"),
+            other => panic!("expected Patch, got {:?}", other),
+        }
+        assert_eq!(other, decals(&["a", "b"]));
+    }
+
+    #[test]
+    fn reapply() {
+        let old = map(vec![level("a-00", vec![entity("spikesUp", 1, 16)])]);
+        let mut new = map(vec![level(
+            "a-00",
+            vec![entity("spikesUp", 1, 24), entity("refill", 2, 0)],
+        )]);
+        new.insert(decals(&["a"]));
+        new.insert(decals(&["b"]));
+        let patch = diff(&old, &new);
+
+        let mut patched = old.clone();
+        patch.apply(&mut patched).unwrap();
+        assert_eq!(patched, new);
+        patch.apply(&mut patched).unwrap();
+        assert_eq!(patched, new);
+
+        let mut removed = new.clone();
+        let reverse = diff(&new, &old);
+        reverse.apply(&mut removed).unwrap();
+        assert_eq!(removed, old);
+        assert!(reverse.apply(&mut removed).is_err());
+        assert_eq!(removed, old);
+    }
+}
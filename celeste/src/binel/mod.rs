@@ -1,4 +1,7 @@
-use hashbrown::HashMap;
+use crate::Result;
+use indexmap::IndexMap;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::prelude::v1::*;
 
 /// `parser` parses `BinaryElement` files.
@@ -10,18 +13,55 @@ pub mod writer;
 /// `serialize` serializes and deserializes `BinEl`s.
 pub mod serialize;
 
+/// `diff` computes and applies differences between `BinEl`s.
+pub mod diff;
+
+/// `query` selects elements from a `BinEl` using paths.
+pub mod query;
+
+/// `stream` reads and writes `BinaryElement` files as streams of events.
+pub mod stream;
+
+/// `visit` traverses trees of `BinEl`s.
+pub mod visit;
+
+/// `xml` converts `BinaryElement` files to and from XML.
+#[cfg(feature = "xml")]
+pub mod xml;
+
+mod decode;
+
+mod aio;
+pub use aio::*;
+
+mod borrowed;
+pub use borrowed::*;
+
+mod builder;
+pub use builder::*;
+
 /// Holds `BinaryElement` files.
 #[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct BinFile {
     /// The file's "package". Usually the name of the file it is stored in.
     pub package: String,
     /// The root element of the file.
     pub root: BinEl,
+    /// The string lookup table to write the file with. Filled in by
+    /// `parser::take_file_lossless`. If this is `None`, a new lookup table is
+    /// generated when writing.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub lookup: Option<Vec<String>>,
 }
 
 /// A value stored in an attribute inside a `BinEl`. Unlike XML, attributes are
 /// strongly typed.
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[allow(missing_docs)]
 pub enum BinElAttr {
     Bool(bool),
@@ -30,14 +70,81 @@ pub enum BinElAttr {
     Text(String),
 }
 
-/// An element stored in a `BinFile`. Based on XML.
-#[derive(PartialEq, Debug, Clone, Default)]
+/// The tag a `BinElAttr` is stored with in a `BinaryElement` file. Several tags
+/// may decode to the same `BinElAttr`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum BinElAttrTag {
+    /// A bool, stored as a u8.
+    Bool = 0x00,
+    /// An int, stored as a u8.
+    U8 = 0x01,
+    /// An int, stored as an i16.
+    I16 = 0x02,
+    /// An int, stored as an i32.
+    I32 = 0x03,
+    /// A float, stored as an f32.
+    Float = 0x04,
+    /// A string, stored as an index into the lookup table.
+    Lookup = 0x05,
+    /// A string, stored with a varint length.
+    String = 0x06,
+    /// A string, stored in Celeste's RLE format.
+    Rle = 0x07,
+}
+
+impl BinElAttrTag {
+    /// Get the tag corresponding to a byte, if it is valid.
+    pub fn from_u8(byte: u8) -> Option<Self> {
+        use BinElAttrTag::*;
+        Some(match byte {
+            0x00 => Bool,
+            0x01 => U8,
+            0x02 => I16,
+            0x03 => I32,
+            0x04 => Float,
+            0x05 => Lookup,
+            0x06 => String,
+            0x07 => Rle,
+            _ => return None,
+        })
+    }
+}
+
+/// An element stored in a `BinFile`. Based on XML. Both attributes and
+/// children are kept in document order, so a parsed file is written back out
+/// in the same order. Equality ignores `tags`, so a file parsed losslessly is
+/// equal to the same file parsed normally.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct BinEl {
     /// The name of the `BinEl`.
     pub name: String,
     /// All attributes of the `BinEl`. Unlike XML, these are strongly typed.
-    pub attributes: HashMap<String, BinElAttr>,
-    children: HashMap<String, Vec<BinEl>>,
+    pub attributes: IndexMap<String, BinElAttr>,
+    /// The tags that attributes were stored with, keyed by attribute name.
+    /// Filled in by `parser::take_file_lossless`, and used by the writer
+    /// whenever the tag can still hold the attribute's value. Attributes
+    /// without a tag are encoded as compactly as possible.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "IndexMap::is_empty")
+    )]
+    pub tags: IndexMap<String, BinElAttrTag>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    children: Vec<BinEl>,
+}
+
+impl PartialEq for BinEl {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.attributes == other.attributes
+            && self.children == other.children
+    }
 }
 
 impl BinEl {
@@ -46,8 +153,9 @@ impl BinEl {
     pub fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
-            children: HashMap::new(),
-            attributes: HashMap::new(),
+            children: Vec::new(),
+            attributes: IndexMap::new(),
+            tags: IndexMap::new(),
         }
     }
 
@@ -55,7 +163,7 @@ impl BinEl {
     #[inline]
     pub fn text(&self) -> Option<&String> {
         match self.attributes.get("innerText")? {
-            BinElAttr::Text(text) => Some(&text),
+            BinElAttr::Text(text) => Some(text),
             _ => None,
         }
     }
@@ -76,43 +184,83 @@ impl BinEl {
             .insert("innerText".to_string(), BinElAttr::Text(text.to_string()))
     }
 
-    /// Add a child to the `BinEl`.
+    /// Add a child to the end of the `BinEl`.
     #[inline]
     pub fn insert(&mut self, child: Self) {
-        self.get_mut(&child.name).push(child);
+        self.children.push(child);
     }
 
-    /// Get all children of the `BinEl`.
+    /// Get all children of the `BinEl`, in document order.
     #[inline]
-    pub fn children<'a>(&'a self) -> impl Iterator<Item = &Self> + 'a {
-        self.children.values().flatten()
+    pub fn children<'a>(&'a self) -> impl Iterator<Item = &'a Self> + 'a {
+        self.children.iter()
     }
 
-    /// Get all children of the `BinEl`, mutable.
+    /// Get all children of the `BinEl` in document order, mutable.
     #[inline]
-    pub fn children_mut<'a>(&'a mut self) -> impl Iterator<Item = &mut Self> + 'a {
-        self.children.values_mut().flatten()
+    pub fn children_mut<'a>(&'a mut self) -> impl Iterator<Item = &'a mut Self> + 'a {
+        self.children.iter_mut()
     }
 
-    /// Get children of the `BinEl` by name.
+    /// Get children of the `BinEl` by name, in document order.
     #[inline]
-    pub fn get(&self, name: &str) -> &Vec<Self> {
-        static CHILDLESS_BINEL_VEC: Vec<BinEl> = Vec::new();
-        self.children.get(name).unwrap_or(&CHILDLESS_BINEL_VEC)
+    pub fn get<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Self> + 'a {
+        self.children().filter(move |child| child.name == name)
     }
 
-    /// Get mutable children of the `BinEl` by name.
+    /// Get mutable children of the `BinEl` by name, in document order.
     #[inline]
-    pub fn get_mut(&mut self, name: &str) -> &mut Vec<Self> {
-        self.children
-            .entry(name.to_string())
-            .or_insert_with(Vec::new)
+    pub fn get_mut<'a>(&'a mut self, name: &'a str) -> impl Iterator<Item = &'a mut Self> + 'a {
+        self.children_mut().filter(move |child| child.name == name)
     }
 
-    /// Drain all children of the `BinEl`.
+    /// Drain all children of the `BinEl`, in document order.
     #[inline]
     pub fn drain<'a>(&'a mut self) -> impl Iterator<Item = Self> + 'a {
-        self.children.drain().map(|(_k, v)| v).flatten()
+        self.children.drain(..)
+    }
+
+    /// Select descendants of the `BinEl` using a query, such as
+    /// `levels/level[@name='a-01']/entities/*[@id>5]`, in document order. See
+    /// `query::Query` for the syntax.
+    ///
+    /// # Examples:
+    /// ```
+    /// use celeste::binel::*;
+    ///
+    /// let mut levels = BinEl::new("levels");
+    /// levels.insert(BinEl::new("level"));
+    /// let mut map = BinEl::new("Map");
+    /// map.insert(levels);
+    ///
+    /// assert_eq!(map.select("levels/level").unwrap().count(), 1);
+    /// assert_eq!(map.select("//*").unwrap().count(), 2);
+    /// ```
+    pub fn select<'a>(
+        &'a self,
+        query: &str,
+    ) -> Result<'static, impl Iterator<Item = &'a Self> + 'a> {
+        Ok(query::Query::parse(query)?.select(self))
+    }
+
+    /// Select descendants of the `BinEl` using a query in document order,
+    /// mutable. Matching elements inside of another matching element are
+    /// skipped. See `query::Query` for the syntax.
+    pub fn select_mut<'a>(
+        &'a mut self,
+        query: &str,
+    ) -> Result<'static, impl Iterator<Item = &'a mut Self> + 'a> {
+        Ok(query::Query::parse(query)?.select_mut(self))
+    }
+}
+
+// Elements in error paths are indexed among their siblings of the same name,
+// leaving out the index of the first, such as `Map/levels/level[3]/entities`.
+pub(crate) fn path_segment(name: &str, position: usize) -> String {
+    if position == 0 {
+        name.to_string()
+    } else {
+        format!("{}[{}]", name, position)
     }
 }
 
@@ -125,6 +273,7 @@ mod test {
         let mut file = BinFile {
             package: "pkg".to_string(),
             root: BinEl::new("root"),
+            lookup: None,
         };
         file.root.insert(BinEl::new("one"));
         file.root.insert(BinEl::new("two"));
@@ -132,27 +281,57 @@ mod test {
 
     #[test]
     fn get_child() {
-        let empty_binel: Vec<BinEl> = vec![];
         let mut file = BinFile {
             package: "pkg".to_string(),
             root: BinEl::new("root"),
+            lookup: None,
         };
         file.root.insert(BinEl::new("one"));
         file.root.insert(BinEl::new("two"));
-        assert_eq!(file.root.get_mut("one")[0].set_text("hello"), None);
         assert_eq!(
-            file.root.get_mut("two")[0]
+            file.root.get_mut("one").next().unwrap().set_text("hello"),
+            None
+        );
+        assert_eq!(
+            file.root
+                .get_mut("two")
+                .next()
+                .unwrap()
                 .attributes
                 .insert("word".to_string(), BinElAttr::Text("world".to_string())),
             None
         );
 
-        assert_eq!(file.root.get("one")[0].text(), Some(&"hello".to_string()));
-        assert_eq!(file.root.get("two")[0].text(), None);
         assert_eq!(
-            file.root.get("two")[0].attributes.get("word"),
+            file.root.get("one").next().unwrap().text(),
+            Some(&"hello".to_string())
+        );
+        assert_eq!(file.root.get("two").next().unwrap().text(), None);
+        assert_eq!(
+            file.root.get("two").next().unwrap().attributes.get("word"),
             Some(&BinElAttr::Text("world".to_string()))
         );
-        assert_eq!(file.root.get("three"), &empty_binel);
+        assert_eq!(file.root.get("three").count(), 0);
+    }
+
+    #[test]
+    fn child_order() {
+        let mut root = BinEl::new("root");
+        root.insert(BinEl::new("one"));
+        root.insert(BinEl::new("two"));
+        root.insert(BinEl::new("one"));
+        root.attributes.insert("z".to_string(), BinElAttr::Int(0));
+        root.attributes.insert("a".to_string(), BinElAttr::Int(1));
+
+        let names: Vec<_> = root.children().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["one", "two", "one"]);
+        assert_eq!(root.get("one").count(), 2);
+
+        let keys: Vec<_> = root.attributes.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["z", "a"]);
+
+        let drained: Vec<_> = root.drain().map(|e| e.name).collect();
+        assert_eq!(drained, ["one", "two", "one"]);
+        assert_eq!(root.children().count(), 0);
     }
 }
@@ -1,14 +1,35 @@
+use super::decode::{self, Payload, Varint};
 use super::*;
-use nom::branch::alt;
+use crate::{Error, Result};
 use nom::bytes::complete::*;
 use nom::combinator::{map, map_opt, map_res};
+use nom::error::{ErrorKind, ParseError};
 use nom::multi::{count, length_data};
 use nom::number::complete::*;
-use nom::sequence::preceded;
-use nom::{error::ParseError, IResult};
-use nom_varint::take_varint;
+use nom::IResult;
+use std::borrow::Cow;
 use std::str::from_utf8;
 
+fn take_varint<'a, E>(buf: &'a [u8]) -> IResult<&'a [u8], usize, E>
+where
+    E: ParseError<&'a [u8]>,
+{
+    let mut varint = Varint::default();
+    for (i, &byte) in buf.iter().enumerate() {
+        match varint.push(byte) {
+            Ok(Some(value)) => return Ok((&buf[i + 1..], value)),
+            Ok(None) => {}
+            Err(_) => {
+                return Err(nom::Err::Error(E::from_error_kind(
+                    buf,
+                    ErrorKind::TooLarge,
+                )))
+            }
+        }
+    }
+    Err(nom::Err::Error(E::from_error_kind(buf, ErrorKind::Eof)))
+}
+
 /// Take a borrowed string with the length being a varint.
 ///
 /// # Examples:
@@ -46,33 +67,83 @@ where
 }
 
 /// Lookup a u16 from a `&[u8]` in a string lookup table.
-pub fn take_lookup<'a: 'b, 'b, E: 'b>(
+pub fn take_lookup<'a: 'b, 'b, E>(
     lookup: &'b [String],
 ) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], &'b String, E> + 'b
 where
-    E: ParseError<&'a [u8]>,
+    E: ParseError<&'a [u8]> + 'b,
 {
     map_opt(le_u16, move |index| lookup.get(index as usize))
 }
 
-/// Take a single character from a Celeste RLE-encoded string in a `&[u8]`.
+/// Lookup a u16 from a `&[u8]` in a borrowed string lookup table.
+pub fn take_lookup_str<'a: 'b, 'b, E>(
+    lookup: &'b [&'a str],
+) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], &'a str, E> + 'b
+where
+    E: ParseError<&'a [u8]> + 'b,
+{
+    map_opt(le_u16, move |index| lookup.get(index as usize).copied())
+}
+
+/// Take a single run of characters from a Celeste RLE-encoded string in a
+/// `&[u8]`. Each character is stored as a single Latin-1 byte, which maps
+/// directly to a `char`.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::parser::take_rle_char;
+/// use celeste::Error;
+///
+/// assert_eq!(take_rle_char::<Error>(b"\x03\xe9").unwrap(), (&b""[..], "ééé".to_string()));
+/// ```
 pub fn take_rle_char<'a, E>(buf: &'a [u8]) -> IResult<&'a [u8], String, E>
 where
     E: ParseError<&'a [u8]>,
 {
-    let (buf, times) = le_u8(buf)?;
-    let (buf, byte) = le_u8(buf)?;
-    Ok((buf, (byte as char).to_string().repeat(times as usize)))
+    map(take(2usize), decode::decode_rle)(buf)
 }
 
-/// Take a Celeste RLE-encoded string from a `&[u8]`
+/// Take a Celeste RLE-encoded string from a `&[u8]`. The length must be even
+/// and not negative.
 pub fn take_rle_string<'a, E>(buf: &'a [u8]) -> IResult<&'a [u8], String, E>
 where
     E: ParseError<&'a [u8]>,
 {
-    let (buf, len) = le_i16(buf)?;
-    let (buf, chars) = count(take_rle_char, (len / 2) as usize)(buf)?;
-    Ok((buf, chars.concat()))
+    let (buf, len) = map_opt(le_i16, decode::rle_len)(buf)?;
+    map(take(len), decode::decode_rle)(buf)
+}
+
+/// Parse a `BinElAttr` from a `&[u8]`, along with the tag it was stored with.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::*;
+/// use celeste::Error;
+///
+/// assert_eq!(
+///     parser::take_tagged_elemattr::<Error>(&[])(b"\x02\x05\x00").unwrap(),
+///     (&b""[..], (BinElAttrTag::I16, BinElAttr::Int(5)))
+/// );
+/// ```
+pub fn take_tagged_elemattr<'a: 'b, 'b, E>(
+    lookup: &'b [String],
+) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], (BinElAttrTag, BinElAttr), E> + 'b
+where
+    E: ParseError<&'a [u8]> + 'b,
+{
+    move |buf| {
+        let (buf, tag) = map_opt(le_u8, BinElAttrTag::from_u8)(buf)?;
+        let (buf, attr) = match tag.payload() {
+            Payload::Number(len) => map(take(len), |bytes| tag.decode_number(bytes))(buf)?,
+            Payload::Lookup => map(take_lookup(lookup), |string: &String| {
+                BinElAttr::Text(string.clone())
+            })(buf)?,
+            Payload::String => map(take_string, BinElAttr::Text)(buf)?,
+            Payload::Rle => map(take_rle_string, BinElAttr::Text)(buf)?,
+        };
+        Ok((buf, (tag, attr)))
+    }
 }
 
 /// Parse a `BinElAttr` from a `&[u8]`.
@@ -84,40 +155,73 @@ where
 ///
 /// assert_eq!(parser::take_elemattr::<Error>(&[])(b"\x01\x05").unwrap(), ((&b""[..], BinElAttr::Int(5))));
 /// ```
-#[allow(clippy::unknown_clippy_lints)]
-#[allow(renamed_and_removed_lints)]
-#[allow(clippy::cognitive_complexity)]
-#[allow(clippy::cyclomatic_complexity)]
-pub fn take_elemattr<'a: 'b, 'b, E: 'b>(
+pub fn take_elemattr<'a: 'b, 'b, E>(
     lookup: &'b [String],
 ) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], BinElAttr, E> + 'b
+where
+    E: ParseError<&'a [u8]> + 'b,
+{
+    map(take_tagged_elemattr(lookup), |(_, attr)| attr)
+}
+
+// On failure, `path` is left holding the elements that were being parsed.
+fn parse_element<'a: 'b, 'b, E>(
+    buf: &'a [u8],
+    lookup: &'b [String],
+    lossless: bool,
+    path: &mut Vec<Cow<'b, str>>,
+) -> IResult<&'a [u8], BinEl, E>
+where
+    E: ParseError<&'a [u8]> + 'b,
+{
+    let (mut buf, name) = take_lookup(lookup)(buf)?;
+    path.push(Cow::Borrowed(name));
+
+    let mut binel = BinEl::new(name);
+
+    let (attr_count_buf, attr_count) = le_u8(buf)?;
+    buf = attr_count_buf;
+
+    for _ in 0..attr_count {
+        let (key_buf, key) = take_lookup(lookup)(buf)?;
+        let (val_buf, (tag, val)) = take_tagged_elemattr(lookup)(key_buf)?;
+        buf = val_buf;
+        if lossless {
+            binel.tags.insert(key.clone(), tag);
+        }
+        binel.attributes.insert(key.clone(), val);
+    }
+
+    let (mut buf, child_count) = le_u16(buf)?;
+    for _ in 0..child_count {
+        let depth = path.len();
+        match parse_element(buf, lookup, lossless, path) {
+            Ok((child_buf, child)) => {
+                buf = child_buf;
+                binel.insert(child);
+            }
+            Err(err) => {
+                if let Some(segment) = path.get_mut(depth) {
+                    let position = binel.get(segment).count();
+                    *segment = Cow::Owned(path_segment(segment, position));
+                }
+                return Err(err);
+            }
+        }
+    }
+
+    path.pop();
+    Ok((buf, binel))
+}
+
+fn element<'a: 'b, 'b, E>(
+    lookup: &'b [String],
+    lossless: bool,
+) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], BinEl, E> + 'b
 where
     E: ParseError<&'a [u8]>,
 {
-    alt((
-        preceded(
-            tag(b"\x00"),
-            map(le_u8, |byte: u8| BinElAttr::Bool(byte != 0)),
-        ),
-        preceded(
-            tag(b"\x01"),
-            map(le_u8, |byte: u8| BinElAttr::Int(i32::from(byte))),
-        ),
-        preceded(
-            tag(b"\x02"),
-            map(le_i16, |word: i16| BinElAttr::Int(i32::from(word))),
-        ),
-        preceded(tag(b"\x03"), map(le_i32, BinElAttr::Int)),
-        preceded(tag(b"\x04"), map(le_f32, BinElAttr::Float)),
-        preceded(
-            tag(b"\x05"),
-            map(take_lookup(lookup), |string: &String| {
-                BinElAttr::Text(string.clone())
-            }),
-        ),
-        preceded(tag(b"\x06"), map(take_string, BinElAttr::Text)),
-        preceded(tag(b"\x07"), map(take_rle_string, BinElAttr::Text)),
-    ))
+    move |buf| parse_element(buf, lookup, lossless, &mut Vec::new())
 }
 
 /// Parse a `BinEl` from a `&[u8]`. Tested solely in integration tests due to
@@ -127,28 +231,83 @@ pub fn take_element<'a: 'b, 'b, E>(
 ) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], BinEl, E> + 'b
 where
     E: ParseError<&'a [u8]>,
+{
+    element(lookup, false)
+}
+
+/// Parse a `BinEl` from a `&[u8]`, recording the tag of every attribute in
+/// `BinEl::tags`. Tested solely in integration tests due to complexity.
+pub fn take_element_lossless<'a: 'b, 'b, E>(
+    lookup: &'b [String],
+) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], BinEl, E> + 'b
+where
+    E: ParseError<&'a [u8]>,
+{
+    element(lookup, true)
+}
+
+/// Parse a `BinElAttrRef` from a `&[u8]`, borrowing strings from the input
+/// where possible.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::*;
+/// use celeste::Error;
+///
+/// assert_eq!(
+///     parser::take_elemattr_ref::<Error>(&["hi"])(b"\x05\x00\x00").unwrap(),
+///     (&b""[..], BinElAttrRef::Text("hi".into()))
+/// );
+/// ```
+pub fn take_elemattr_ref<'a: 'b, 'b, E>(
+    lookup: &'b [&'a str],
+) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], BinElAttrRef<'a>, E> + 'b
+where
+    E: ParseError<&'a [u8]> + 'b,
 {
     move |buf| {
-        let mut buf = buf;
+        let (buf, tag) = map_opt(le_u8, BinElAttrTag::from_u8)(buf)?;
+        match tag.payload() {
+            Payload::Number(len) => map(take(len), |bytes| tag.decode_number(bytes))(buf),
+            Payload::Lookup => map(take_lookup_str(lookup), |string| {
+                BinElAttrRef::Text(Cow::Borrowed(string))
+            })(buf),
+            Payload::String => {
+                map(take_str, |string| BinElAttrRef::Text(Cow::Borrowed(string)))(buf)
+            }
+            Payload::Rle => map(take_rle_string, |string| {
+                BinElAttrRef::Text(Cow::Owned(string))
+            })(buf),
+        }
+    }
+}
 
-        let (lookup_buf, name) = take_lookup(&lookup)(buf)?;
-        buf = lookup_buf;
+/// Parse a `BinElRef` from a `&[u8]`, borrowing from the input. Tested solely
+/// in integration tests due to complexity.
+pub fn take_element_ref<'a: 'b, 'b, E>(
+    lookup: &'b [&'a str],
+) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], BinElRef<'a>, E> + 'b
+where
+    E: ParseError<&'a [u8]> + 'b,
+{
+    move |buf| {
+        let (mut buf, name) = take_lookup_str(lookup)(buf)?;
 
-        let mut binel = BinEl::new(&name);
+        let mut binel = BinElRef::new(name);
 
         let (attr_count_buf, attr_count) = le_u8(buf)?;
         buf = attr_count_buf;
 
         for _ in 0..attr_count {
-            let (key_buf, key) = take_lookup(&lookup)(buf)?;
-            let (val_buf, val) = take_elemattr(&lookup)(key_buf)?;
+            let (key_buf, key) = take_lookup_str(lookup)(buf)?;
+            let (val_buf, val) = take_elemattr_ref(lookup)(key_buf)?;
             buf = val_buf;
-            binel.attributes.insert(key.clone(), val);
+            binel.attributes.insert(key, val);
         }
 
         let (mut buf, child_count) = le_u16(buf)?;
         for _ in 0..child_count {
-            let (child_buf, child) = take_element(lookup)(buf)?;
+            let (child_buf, child) = take_element_ref(lookup)(buf)?;
             buf = child_buf;
             binel.insert(child);
         }
@@ -157,9 +316,28 @@ where
     }
 }
 
-/// Parse a `BinFile` from a `&[u8]`. Tested solely in integration tests due to
-/// complexity.
-pub fn take_file<'a, E>(buf: &'a [u8]) -> IResult<&'a [u8], BinFile, E>
+/// Parse a `BinFileRef` from a `&[u8]`, borrowing from the input. Tested
+/// solely in integration tests due to complexity.
+pub fn take_file_ref<'a, E>(buf: &'a [u8]) -> IResult<&'a [u8], BinFileRef<'a>, E>
+where
+    E: ParseError<&'a [u8]>,
+{
+    #[cfg(not(fuzzing))]
+    let (buf, _) = tag(b"\x0bCELESTE MAP")(buf)?;
+    #[cfg(fuzzing)]
+    let (buf, _) = take_str(buf)?;
+    let (buf, package) = take_str(buf)?;
+    let (buf, length) = le_i16(buf)?;
+    let (buf, lookup) = count(take_str, length as usize)(buf)?;
+    let (buf, root) = take_element_ref(&lookup)(buf)?;
+    Ok((buf, BinFileRef { package, root }))
+}
+
+fn file<'a, E>(
+    buf: &'a [u8],
+    lossless: bool,
+    path: &mut Vec<String>,
+) -> IResult<&'a [u8], BinFile, E>
 where
     E: ParseError<&'a [u8]>,
 {
@@ -170,8 +348,83 @@ where
     let (buf, package) = take_string(buf)?;
     let (buf, length) = le_i16(buf)?;
     let (buf, lookup) = count(take_string, length as usize)(buf)?;
-    let (buf, root) = take_element(&lookup)(buf)?;
-    Ok((buf, BinFile { package, root }))
+
+    let mut element_path = Vec::new();
+    let (buf, root) = match parse_element(buf, &lookup, lossless, &mut element_path) {
+        Ok(ok) => ok,
+        Err(err) => {
+            path.extend(element_path.into_iter().map(Cow::into_owned));
+            return Err(err);
+        }
+    };
+
+    let lookup = if lossless { Some(lookup) } else { None };
+    Ok((
+        buf,
+        BinFile {
+            package,
+            root,
+            lookup,
+        },
+    ))
+}
+
+fn parse(buf: &[u8], lossless: bool) -> Result<'static, BinFile> {
+    let mut path = Vec::new();
+    match file::<(&[u8], ErrorKind)>(buf, lossless, &mut path) {
+        Ok((_, bin)) => Ok(bin),
+        Err(nom::Err::Incomplete(_)) => Err(Error::Incomplete),
+        Err(nom::Err::Error((input, kind))) | Err(nom::Err::Failure((input, kind))) => {
+            Err(Error::ParseFile {
+                offset: buf.len() - input.len(),
+                path: path.join("/"),
+                kind,
+            })
+        }
+    }
+}
+
+/// Parse a `BinFile` from a `&[u8]`. Tested solely in integration tests due to
+/// complexity.
+pub fn take_file<'a, E>(buf: &'a [u8]) -> IResult<&'a [u8], BinFile, E>
+where
+    E: ParseError<&'a [u8]>,
+{
+    file(buf, false, &mut Vec::new())
+}
+
+/// Parse a `BinFile` from a `&[u8]`, keeping the original lookup table and
+/// attribute tags. Writing the result with `writer::put_file` reproduces the
+/// input byte-for-byte. Tested solely in integration tests due to complexity.
+pub fn take_file_lossless<'a, E>(buf: &'a [u8]) -> IResult<&'a [u8], BinFile, E>
+where
+    E: ParseError<&'a [u8]>,
+{
+    file(buf, true, &mut Vec::new())
+}
+
+/// Parse a `BinFile` from a `&[u8]`. Unlike `take_file`, errors are returned as
+/// `Error::ParseFile`, which includes the byte offset and the path to the
+/// element being parsed.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::parser::parse_file;
+///
+/// let mut map_bytes = include_bytes!("../../tests/empty.bin").to_vec();
+/// map_bytes.truncate(map_bytes.len() - 10);
+///
+/// let err = parse_file(&map_bytes).unwrap_err();
+/// assert!(err.to_string().contains("in `Map/"));
+/// ```
+pub fn parse_file(buf: &[u8]) -> Result<'static, BinFile> {
+    parse(buf, false)
+}
+
+/// Parse a `BinFile` from a `&[u8]` like `take_file_lossless`, returning
+/// errors the same way as `parse_file`.
+pub fn parse_file_lossless(buf: &[u8]) -> Result<'static, BinFile> {
+    parse(buf, true)
 }
 
 #[cfg(test)]
@@ -179,6 +432,46 @@ mod test {
     use super::*;
     use celeste::Error;
 
+    #[test]
+    fn parse_error_path() {
+        let mut levels = BinEl::new("levels");
+        for _ in 0..3 {
+            levels.insert(BinEl::new("level"));
+        }
+        let mut entities = BinEl::new("entities");
+        entities
+            .attributes
+            .insert("id".to_string(), BinElAttr::Bool(true));
+        let mut level = BinEl::new("level");
+        level.insert(entities);
+        levels.insert(level);
+        let mut root = BinEl::new("Map");
+        root.insert(levels);
+
+        let bin = BinFile {
+            package: "pkg".to_string(),
+            root,
+            lookup: None,
+        };
+        let mut buf = Vec::new();
+        writer::put_file(&mut buf, &bin).unwrap();
+        assert_eq!(parse_file(&buf).unwrap(), bin);
+
+        // the tag of `id`, followed by its value and the child count
+        let offset = buf.len() - 4;
+        buf[offset] = 0xff;
+
+        match parse_file(&buf) {
+            Err(Error::ParseFile {
+                offset: at, path, ..
+            }) => {
+                assert_eq!(at, offset);
+                assert_eq!(path, "Map/levels/level[3]/entities");
+            }
+            other => panic!("expected ParseFile, got {:?}", other),
+        }
+    }
+
     #[test]
     fn take_header() {
         assert_eq!(
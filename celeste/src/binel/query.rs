@@ -0,0 +1,487 @@
+use super::*;
+use crate::{Error, Result};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// A parsed query for selecting elements from a `BinEl`, using a small subset
+/// of XPath.
+///
+/// A query is a list of steps separated by `/`, each matching children of the
+/// elements matched by the previous step, starting from the element the query
+/// is run on. Steps separated by `//` match descendants at any depth instead.
+/// Each step is either an element name or `*`, followed by any number of
+/// predicates:
+///
+/// * `[@attr]` matches elements with the attribute.
+/// * `[@attr=value]` compares the attribute with `=`, `!=`, `<`, `<=`, `>` or
+///   `>=`. Values may be quoted strings, numbers, `true` or `false`. Numbers
+///   compare with ints, floats and numeric text.
+/// * `[n]` matches the `n`th (starting from 0) element matched so far with the
+///   same parent, so `level[3]` is the fourth level.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::{query::Query, *};
+///
+/// let mut entities = BinEl::new("entities");
+/// for id in 0..10 {
+///     let mut spikes = BinEl::new("spikesUp");
+///     spikes.attributes.insert("id".to_string(), BinElAttr::Int(id));
+///     entities.insert(spikes);
+/// }
+///
+/// let mut level = BinEl::new("level");
+/// level.attributes.insert("name".to_string(), BinElAttr::Text("a-01".to_string()));
+/// level.insert(entities);
+///
+/// let mut levels = BinEl::new("levels");
+/// levels.insert(level);
+///
+/// let mut map = BinEl::new("Map");
+/// map.insert(levels);
+///
+/// let query = Query::parse("levels/level[@name='a-01']/entities/*[@id>5]").unwrap();
+/// assert_eq!(query.select(&map).count(), 4);
+/// assert_eq!(map.select("//spikesUp[2]").unwrap().count(), 1);
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct Query {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+struct Step {
+    descendant: bool,
+    // `None` for `*`
+    name: Option<String>,
+    predicates: Vec<Predicate>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Predicate {
+    Index(usize),
+    Has(String),
+    Compare(String, Op, Value),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Value {
+    Bool(bool),
+    Number(f64),
+    Text(String),
+}
+
+struct QueryParser<'a> {
+    query: &'a str,
+    position: usize,
+}
+
+impl<'a> QueryParser<'a> {
+    fn error(&self, message: impl Into<String>) -> Error<'static> {
+        Error::ParseQuery {
+            position: self.position,
+            message: message.into(),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.query[self.position..]
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        let len = rest.find(|c| !pred(c)).unwrap_or(rest.len());
+        self.position += len;
+        &rest[..len]
+    }
+
+    fn name(&mut self, what: &str) -> Result<'static, String> {
+        let name = self.take_while(|c| !"/[]*@=!<>'\"".contains(c) && !c.is_whitespace());
+        if name.is_empty() {
+            Err(self.error(format!("Expected {}", what)))
+        } else {
+            Ok(name.to_string())
+        }
+    }
+
+    fn value(&mut self) -> Result<'static, Value> {
+        for quote in &["'", "\""] {
+            if self.eat(quote) {
+                let text = self.take_while(|c| !quote.contains(c));
+                if !self.eat(quote) {
+                    return Err(self.error("Unterminated string"));
+                }
+                return Ok(Value::Text(text.to_string()));
+            }
+        }
+
+        let word = self.take_while(|c| c != ']').trim_end();
+        if word.is_empty() {
+            Err(self.error("Expected a value"))
+        } else if word == "true" || word == "false" {
+            Ok(Value::Bool(word == "true"))
+        } else if let Ok(number) = word.parse() {
+            Ok(Value::Number(number))
+        } else {
+            Ok(Value::Text(word.to_string()))
+        }
+    }
+
+    fn predicate(&mut self) -> Result<'static, Predicate> {
+        self.skip_whitespace();
+
+        if !self.eat("@") {
+            let index = self.take_while(|c| c.is_ascii_digit());
+            return index
+                .parse()
+                .map(Predicate::Index)
+                .map_err(|_| self.error("Expected an attribute or index"));
+        }
+
+        let attr = self.name("an attribute name")?;
+        self.skip_whitespace();
+
+        let ops = [
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("=", Op::Eq),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+        let op = match ops.iter().find(|(token, _)| self.eat(token)) {
+            Some((_, op)) => *op,
+            None => return Ok(Predicate::Has(attr)),
+        };
+
+        self.skip_whitespace();
+        Ok(Predicate::Compare(attr, op, self.value()?))
+    }
+
+    fn step(&mut self, descendant: bool) -> Result<'static, Step> {
+        let name = if self.eat("*") {
+            None
+        } else {
+            Some(self.name("an element name or *")?)
+        };
+
+        let mut predicates = Vec::new();
+        while self.eat("[") {
+            predicates.push(self.predicate()?);
+            self.skip_whitespace();
+            if !self.eat("]") {
+                return Err(self.error("Expected ]"));
+            }
+        }
+
+        Ok(Step {
+            descendant,
+            name,
+            predicates,
+        })
+    }
+
+    fn query(&mut self) -> Result<'static, Query> {
+        let mut steps = Vec::new();
+        let mut descendant = self.eat("//");
+
+        loop {
+            steps.push(self.step(descendant)?);
+
+            if self.position == self.query.len() {
+                return Ok(Query { steps });
+            }
+
+            descendant = self.eat("//");
+            if !descendant && !self.eat("/") {
+                return Err(self.error("Expected / or the end of the query"));
+            }
+        }
+    }
+}
+
+fn compare(attr: &BinElAttr, value: &Value) -> Option<Ordering> {
+    match (attr, value) {
+        (BinElAttr::Bool(attr), Value::Bool(value)) => Some(attr.cmp(value)),
+        (BinElAttr::Int(attr), Value::Number(value)) => f64::from(*attr).partial_cmp(value),
+        (BinElAttr::Float(attr), Value::Number(value)) => f64::from(*attr).partial_cmp(value),
+        (BinElAttr::Text(attr), Value::Number(value)) => {
+            attr.parse::<f64>().ok()?.partial_cmp(value)
+        }
+        (BinElAttr::Text(attr), Value::Text(value)) => Some(attr.as_str().cmp(value)),
+        _ => None,
+    }
+}
+
+impl Predicate {
+    fn matches(&self, elem: &BinEl) -> bool {
+        match self {
+            Predicate::Index(_) => true,
+            Predicate::Has(attr) => elem.attributes.contains_key(attr),
+            Predicate::Compare(attr, op, value) => {
+                let ordering = match elem.attributes.get(attr) {
+                    Some(attr) => compare(attr, value),
+                    None => None,
+                };
+                match (ordering, op) {
+                    (None, Op::Ne) => elem.attributes.contains_key(attr),
+                    (None, _) => false,
+                    (Some(ordering), Op::Eq) => ordering == Ordering::Equal,
+                    (Some(ordering), Op::Ne) => ordering != Ordering::Equal,
+                    (Some(ordering), Op::Lt) => ordering == Ordering::Less,
+                    (Some(ordering), Op::Le) => ordering != Ordering::Greater,
+                    (Some(ordering), Op::Gt) => ordering == Ordering::Greater,
+                    (Some(ordering), Op::Ge) => ordering != Ordering::Less,
+                }
+            }
+        }
+    }
+}
+
+impl Step {
+    // Matches children of `parent`, pushing their paths.
+    fn match_children(&self, parent: &BinEl, path: &[usize], out: &mut Vec<Vec<usize>>) {
+        let mut candidates: Vec<usize> = (0..parent.children.len())
+            .filter(|&i| match &self.name {
+                Some(name) => parent.children[i].name == *name,
+                None => true,
+            })
+            .collect();
+
+        for predicate in &self.predicates {
+            candidates = match predicate {
+                Predicate::Index(index) => candidates.get(*index).copied().into_iter().collect(),
+                predicate => candidates
+                    .into_iter()
+                    .filter(|&i| predicate.matches(&parent.children[i]))
+                    .collect(),
+            };
+        }
+
+        for i in candidates {
+            let mut child_path = path.to_vec();
+            child_path.push(i);
+            out.push(child_path);
+        }
+    }
+
+    fn match_descendants(&self, parent: &BinEl, path: &mut Vec<usize>, out: &mut Vec<Vec<usize>>) {
+        self.match_children(parent, path, out);
+        for (i, child) in parent.children.iter().enumerate() {
+            path.push(i);
+            self.match_descendants(child, path, out);
+            path.pop();
+        }
+    }
+}
+
+fn resolve<'a>(mut elem: &'a BinEl, path: &[usize]) -> &'a BinEl {
+    for &i in path {
+        elem = &elem.children[i];
+    }
+    elem
+}
+
+// `paths` must be sorted, with no path being a prefix of another.
+fn resolve_mut<'a>(
+    elem: &'a mut BinEl,
+    paths: &[Vec<usize>],
+    depth: usize,
+    out: &mut Vec<&'a mut BinEl>,
+) {
+    if paths.iter().any(|path| path.len() == depth) {
+        out.push(elem);
+        return;
+    }
+
+    let mut paths = paths;
+    for (i, child) in elem.children.iter_mut().enumerate() {
+        let count = paths.iter().take_while(|path| path[depth] == i).count();
+        if count > 0 {
+            resolve_mut(child, &paths[..count], depth + 1, out);
+            paths = &paths[count..];
+        }
+    }
+}
+
+impl Query {
+    /// Parse a query. Returns `Error::ParseQuery` if it is invalid.
+    pub fn parse(query: &str) -> Result<'static, Self> {
+        QueryParser { query, position: 0 }.query()
+    }
+
+    // The paths of all matching elements, in document order.
+    fn paths(&self, root: &BinEl) -> Vec<Vec<usize>> {
+        let mut matched = vec![Vec::new()];
+
+        for step in &self.steps {
+            let mut next = Vec::new();
+            for path in &matched {
+                let parent = resolve(root, path);
+                if step.descendant {
+                    step.match_descendants(parent, &mut path.clone(), &mut next);
+                } else {
+                    step.match_children(parent, path, &mut next);
+                }
+            }
+
+            if step.descendant {
+                let mut seen = HashSet::new();
+                next.retain(|path| seen.insert(path.clone()));
+                next.sort();
+            }
+            matched = next;
+        }
+
+        matched
+    }
+
+    /// Select all matching elements from `root`, in document order.
+    pub fn select<'a>(&self, root: &'a BinEl) -> impl Iterator<Item = &'a BinEl> + 'a {
+        self.paths(root)
+            .into_iter()
+            .map(move |path| resolve(root, &path))
+    }
+
+    /// Select all matching elements from `root` in document order, mutable. As
+    /// they can't be borrowed mutably at the same time, matching elements
+    /// inside of another matching element are skipped.
+    pub fn select_mut<'a>(&self, root: &'a mut BinEl) -> impl Iterator<Item = &'a mut BinEl> + 'a {
+        let mut paths = self.paths(root);
+        let mut outer: Vec<Vec<usize>> = Vec::with_capacity(paths.len());
+        for path in paths.drain(..) {
+            match outer.last() {
+                Some(last) if path.starts_with(last) => {}
+                _ => outer.push(path),
+            }
+        }
+
+        let mut out = Vec::with_capacity(outer.len());
+        resolve_mut(root, &outer, 0, &mut out);
+        out.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn map() -> BinEl {
+        let mut levels = BinEl::new("levels");
+        for (name, ids) in &[("a-00", 0..3), ("a-01", 3..8)] {
+            let mut entities = BinEl::new("entities");
+            for id in ids.clone() {
+                let mut entity = BinEl::new(if id % 2 == 0 { "spikesUp" } else { "refill" });
+                entity
+                    .attributes
+                    .insert("id".to_string(), BinElAttr::Int(id));
+                entities.insert(entity);
+            }
+
+            let mut level = BinEl::new("level");
+            level
+                .attributes
+                .insert("name".to_string(), BinElAttr::Text(name.to_string()));
+            level
+                .attributes
+                .insert("dark".to_string(), BinElAttr::Bool(*name == "a-01"));
+            level.insert(entities);
+            levels.insert(level);
+        }
+
+        let mut map = BinEl::new("Map");
+        map.insert(levels);
+        map
+    }
+
+    fn ids<'a>(elems: impl Iterator<Item = &'a BinEl>) -> Vec<i32> {
+        elems
+            .map(|e| match e.attributes.get("id") {
+                Some(BinElAttr::Int(id)) => *id,
+                _ => -1,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn select_children() {
+        let map = map();
+        let query = Query::parse("levels/level[@name='a-01']/entities/*[@id>5]").unwrap();
+        assert_eq!(ids(query.select(&map)), vec![6, 7]);
+
+        let query = Query::parse("levels/level[@dark=false]/entities/refill").unwrap();
+        assert_eq!(ids(query.select(&map)), vec![1]);
+
+        let query = Query::parse("levels/*[1]/entities/*[@id != 4][0]").unwrap();
+        assert_eq!(ids(query.select(&map)), vec![3]);
+    }
+
+    #[test]
+    fn select_descendants() {
+        let map = map();
+        assert_eq!(ids(map.select("//spikesUp").unwrap()), vec![0, 2, 4, 6]);
+        assert_eq!(ids(map.select("levels//*[@id<=1]").unwrap()), vec![0, 1]);
+        assert_eq!(map.select("//level//entities").unwrap().count(), 2);
+        assert_eq!(map.select("//*").unwrap().count(), 13);
+        assert_eq!(ids(map.select("//entities/*[0]").unwrap()), vec![0, 3]);
+    }
+
+    #[test]
+    fn select_mut_skips_nested() {
+        let mut map = map();
+        for elem in map.select_mut("//*[@id]").unwrap() {
+            elem.attributes.insert("x".to_string(), BinElAttr::Int(8));
+        }
+        assert_eq!(map.select("//*[@x=8]").unwrap().count(), 8);
+
+        let outer: Vec<_> = map
+            .select_mut("//*")
+            .unwrap()
+            .map(|e| e.name.clone())
+            .collect();
+        assert_eq!(outer, vec!["levels"]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (query, position) in &[
+            ("", 0),
+            ("levels/", 7),
+            ("levels[@name='a", 15),
+            ("levels[x]", 7),
+            ("levels[0", 8),
+            ("a b", 1),
+        ] {
+            match Query::parse(query) {
+                Err(Error::ParseQuery { position: at, .. }) => {
+                    assert_eq!(at, *position, "{}", query)
+                }
+                other => panic!("expected ParseQuery for {}, got {:?}", query, other),
+            }
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use super::*;
 use crate::{Error, Result};
+use hashbrown::HashMap;
+use std::collections::VecDeque;
 
 #[cfg(feature = "celeste_derive")]
 pub use celeste_derive::*;
@@ -13,6 +15,31 @@ pub enum BinElValue {
     None,
 }
 
+impl BinElValue {
+    /// Describe the kind of value, for use in error messages.
+    ///
+    /// # Examples:
+    /// ```
+    /// use celeste::binel::{serialize::BinElValue, *};
+    ///
+    /// let value = BinElValue::Attribute(BinElAttr::Int(5));
+    /// assert_eq!(value.describe(), "int attribute");
+    ///
+    /// let value = BinElValue::Element(BinEl::new("level"));
+    /// assert_eq!(value.describe(), "element `level`");
+    /// ```
+    pub fn describe(&self) -> String {
+        match self {
+            BinElValue::Attribute(BinElAttr::Bool(_)) => "bool attribute".to_string(),
+            BinElValue::Attribute(BinElAttr::Int(_)) => "int attribute".to_string(),
+            BinElValue::Attribute(BinElAttr::Float(_)) => "float attribute".to_string(),
+            BinElValue::Attribute(BinElAttr::Text(_)) => "text attribute".to_string(),
+            BinElValue::Element(elem) => format!("element `{}`", elem.name),
+            BinElValue::None => "nothing".to_string(),
+        }
+    }
+}
+
 /// A type that can be serialized to and from a `BinEl`.
 ///
 /// # Deriving
@@ -26,6 +53,12 @@ pub enum BinElValue {
 /// attribute. The attribute is named using `celeste_name`, again defaulting to
 /// mixed case.
 ///
+/// Fields marked `celeste_skip` are left out and filled with their default.
+/// To keep everything else, a field marked `celeste_other` gets the attributes
+/// that no other field took. Fields marked `celeste_order` and
+/// `celeste_child_order` get the names of the attributes and children in the
+/// order they were read, which they are written back in.
+///
 /// Alternatively, if it is a newtype (i.e. tuple struct with single type), then
 /// it will get serialized as the type it is based off of. If this is an
 /// element, it must have the same name as the newtype struct (`celeste_name` is
@@ -57,6 +90,48 @@ pub trait BinElType: Sized {
     }
 }
 
+/// Describe a `BinElType` for use in error messages, given the name of the
+/// type. Used by the derive.
+#[doc(hidden)]
+pub fn describe_type<T: BinElType>(name: &'static str) -> std::borrow::Cow<'static, str> {
+    match T::elem_name() {
+        Some(elem) => format!("element `{}`", elem).into(),
+        None if !T::maybe_elem() => format!("{} attribute", name).into(),
+        None => name.into(),
+    }
+}
+
+/// Sort attributes back into the order they were read. Attributes that
+/// weren't there keep their relative order, after the rest. Used by the
+/// derive.
+#[doc(hidden)]
+pub fn reorder_attributes(attrs: &mut IndexMap<String, BinElAttr>, order: &[String]) {
+    let position = |key: &String| order.iter().position(|e| e == key).unwrap_or(order.len());
+    attrs.sort_by(|a, _, b, _| position(a).cmp(&position(b)));
+}
+
+/// Sort children back into the order they were read, matching the nth child
+/// with a name to the nth time that name was read. Children that weren't
+/// there keep their relative order, after the rest. Used by the derive.
+#[doc(hidden)]
+pub fn reorder_children(children: &mut Vec<BinEl>, order: &[String]) {
+    let mut slots: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for (i, name) in order.iter().enumerate() {
+        slots.entry(name).or_default().push_back(i);
+    }
+    let mut sorted: Vec<_> = children
+        .drain(..)
+        .map(|child| {
+            let slot = slots
+                .get_mut(child.name.as_str())
+                .and_then(VecDeque::pop_front);
+            (slot.unwrap_or(order.len()), child)
+        })
+        .collect();
+    sorted.sort_by_key(|(slot, _)| *slot);
+    children.extend(sorted.into_iter().map(|(_, child)| child));
+}
+
 macro_rules! impl_primitive {
     ($attr:ident, $type:ident, $val:ident) => {
         impl BinElType for $type {
@@ -68,7 +143,10 @@ macro_rules! impl_primitive {
             fn from_binel(binel: BinElValue) -> Result<'static, Self> {
                 match binel {
                     BinElValue::Attribute(BinElAttr::$attr(e)) => Ok(e as $type),
-                    _ => Err(Error::from_name(stringify!($type))),
+                    other => Err(Error::deserialize(
+                        concat!(stringify!($type), " attribute"),
+                        other.describe(),
+                    )),
                 }
             }
 
@@ -88,6 +166,92 @@ impl_primitive!(Int, i32, i32);
 impl_primitive!(Float, f32, f32);
 impl_primitive!(Text, String, String);
 
+/// A number that Celeste reads as a float, but that may be stored as either an
+/// int or a float, as Celeste's packer stores numbers without a fractional part
+/// as ints. The variant it was read as is kept, so it's written back the same
+/// way.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::{serialize::*, *};
+///
+/// let number = Number::from_binel(BinElValue::Attribute(BinElAttr::Float(1.0))).unwrap();
+/// assert_eq!(number.get(), 1.0);
+///
+/// match number.into_binel() {
+///     BinElValue::Attribute(attr) => assert_eq!(attr, BinElAttr::Float(1.0)),
+///     _ => panic!("Didn't get attribute!"),
+/// }
+/// ```
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[allow(missing_docs)]
+pub enum Number {
+    Int(i32),
+    Float(f32),
+}
+
+impl Number {
+    /// The value, as Celeste reads it.
+    pub fn get(self) -> f32 {
+        match self {
+            Number::Int(val) => val as f32,
+            Number::Float(val) => val,
+        }
+    }
+}
+
+impl Default for Number {
+    fn default() -> Self {
+        Number::Int(0)
+    }
+}
+
+impl From<i32> for Number {
+    fn from(val: i32) -> Self {
+        Number::Int(val)
+    }
+}
+
+// New values are stored the way Celeste's packer would store them.
+impl From<f32> for Number {
+    fn from(val: f32) -> Self {
+        let in_range = val >= i32::MIN as f32 && val < i32::MAX as f32;
+        if val.fract() == 0.0 && in_range {
+            Number::Int(val as i32)
+        } else {
+            Number::Float(val)
+        }
+    }
+}
+
+impl From<Number> for BinElAttr {
+    fn from(number: Number) -> Self {
+        match number {
+            Number::Int(val) => BinElAttr::Int(val),
+            Number::Float(val) => BinElAttr::Float(val),
+        }
+    }
+}
+
+impl BinElType for Number {
+    fn into_binel(self) -> BinElValue {
+        BinElValue::Attribute(self.into())
+    }
+
+    fn from_binel(binel: BinElValue) -> Result<'static, Self> {
+        match binel {
+            BinElValue::Attribute(BinElAttr::Int(val)) => Ok(Number::Int(val)),
+            BinElValue::Attribute(BinElAttr::Float(val)) => Ok(Number::Float(val)),
+            other => Err(Error::deserialize("number attribute", other.describe())),
+        }
+    }
+
+    fn maybe_elem() -> bool {
+        false
+    }
+}
+
 impl BinElType for BinEl {
     fn into_binel(self) -> BinElValue {
         BinElValue::Element(self)
@@ -96,7 +260,7 @@ impl BinElType for BinEl {
     fn from_binel(binel: BinElValue) -> Result<'static, Self> {
         match binel {
             BinElValue::Element(e) => Ok(e),
-            _ => Err(Error::from_name("BinElValue")),
+            other => Err(Error::deserialize("element", other.describe())),
         }
     }
 
@@ -113,7 +277,7 @@ impl BinElType for BinElAttr {
     fn from_binel(binel: BinElValue) -> Result<'static, Self> {
         match binel {
             BinElValue::Attribute(e) => Ok(e),
-            _ => Err(Error::from_name("BinElAttr")),
+            other => Err(Error::deserialize("attribute", other.describe())),
         }
     }
 
@@ -133,6 +297,18 @@ impl<T: BinElType> BinElType for Option<T> {
     fn from_binel(binel: BinElValue) -> Result<'static, Self> {
         Ok(T::from_binel(binel).ok())
     }
+
+    fn maybe_attr() -> bool {
+        T::maybe_attr()
+    }
+
+    fn maybe_elem() -> bool {
+        T::maybe_elem()
+    }
+
+    fn elem_name() -> Option<&'static str> {
+        T::elem_name()
+    }
 }
 
 #[cfg(all(test, feature = "derive"))]
@@ -141,6 +317,7 @@ mod test {
 
     use crate::binel::serialize::{BinElType, BinElValue};
     use crate::binel::{BinEl, BinElAttr};
+    use indexmap::IndexMap;
 
     #[derive(Eq, PartialEq, Debug, BinElType)]
     struct EmptyMixedCase {}
@@ -183,6 +360,20 @@ mod test {
         pub kept: String,
     }
 
+    #[derive(PartialEq, Debug, BinElType)]
+    struct Ordered {
+        pub kept: String,
+        pub child: Option<EmptyMixedCase>,
+        #[celeste_child_vec]
+        pub children: Vec<BinEl>,
+        #[celeste_other]
+        pub other: IndexMap<String, BinElAttr>,
+        #[celeste_order]
+        pub order: Vec<String>,
+        #[celeste_child_order]
+        pub child_order: Vec<String>,
+    }
+
     #[derive(PartialEq, Debug, Clone, BinElType)]
     struct Newtype(pub BinEl);
 
@@ -363,8 +554,8 @@ mod test {
         };
 
         assert_eq!(binel.children().count(), 4);
-        assert_eq!(binel.get("oneField").len(), 3);
-        assert_eq!(binel.get("emptyMixedCase").len(), 1);
+        assert_eq!(binel.get("oneField").count(), 3);
+        assert_eq!(binel.get("emptyMixedCase").count(), 1);
 
         for e in binel.get("oneField") {
             assert_eq!(e.children().count(), 0);
@@ -410,7 +601,7 @@ mod test {
         };
 
         assert_eq!(binel.children().count(), 1);
-        assert_eq!(binel.get("emptyMixedCase").len(), 1);
+        assert_eq!(binel.get("emptyMixedCase").count(), 1);
 
         binel
     }
@@ -518,10 +709,63 @@ mod test {
         create_newtype();
     }
 
+    #[test]
+    fn deserialize_errors() {
+        let mut binel = create_recursive();
+        binel
+            .attributes
+            .insert("stringField".to_string(), BinElAttr::Int(5));
+        let err = Recursive::from_binel(BinElValue::Element(binel)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Expected String attribute but received int attribute in `stringField`"
+        );
+
+        let mut binel = create_recursive();
+        binel.drain().for_each(drop);
+        let err = Recursive::from_binel(BinElValue::Element(binel)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Expected element `oneField` but received nothing in `elemField`"
+        );
+
+        let err = Recursive::from_binel(BinElValue::Element(create_attr())).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Expected element `recursive` but received element `oneField`"
+        );
+    }
+
     #[test]
     fn deserialize_newtype() {
         let (binel, newtype) = create_newtype();
         let deserialized = Newtype::from_binel(BinElValue::Element(binel));
         assert_eq!(deserialized.unwrap(), newtype);
     }
+
+    #[test]
+    fn keep_other_and_order() {
+        let binel = BinEl::build("ordered")
+            .attr("extra", 1)
+            .attr("kept", "yes")
+            .attr("last", true)
+            .child(BinEl::new("unknown"))
+            .child(BinEl::new("emptyMixedCase"))
+            .child(BinEl::new("unknown"))
+            .finish();
+
+        let ordered = Ordered::from_binel(BinElValue::Element(binel.clone())).unwrap();
+        assert_eq!(ordered.kept, "yes");
+        assert_eq!(ordered.child, Some(EmptyMixedCase {}));
+        assert_eq!(ordered.children.len(), 2);
+        assert_eq!(ordered.other.len(), 2);
+
+        let written = match ordered.into_binel() {
+            BinElValue::Element(elem) => elem,
+            _ => panic!("Didn't get element!"),
+        };
+        assert_eq!(written, binel);
+        // equality ignores the order of attributes
+        assert!(written.attributes.keys().eq(binel.attributes.keys()));
+    }
 }
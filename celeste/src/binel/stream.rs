@@ -0,0 +1,443 @@
+use super::decode::{self, invalid, Payload};
+use super::*;
+use crate::{Error, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{prelude::*, ErrorKind};
+
+/// An event read by a `BinElReader`, or written by a `BinElWriter`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum BinElEvent {
+    /// The start of an element. It is followed by exactly `children` child
+    /// elements, and then a matching `EndElement`.
+    StartElement {
+        /// The name of the element.
+        name: String,
+        /// All attributes of the element, in document order.
+        attributes: IndexMap<String, BinElAttr>,
+        /// The number of children the element has.
+        children: u16,
+    },
+    /// The end of the most recently started element.
+    EndElement,
+}
+
+// Reads through `take`, so see `decode::check_len`.
+fn read_bytes(reader: &mut dyn Read, len: usize) -> Result<'static, Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.take(len as u64).read_to_end(&mut buf)?;
+    decode::check_len(&buf, len)?;
+    Ok(buf)
+}
+
+fn read_string(reader: &mut dyn Read) -> Result<'static, String> {
+    let mut varint = decode::Varint::default();
+    let length = loop {
+        if let Some(length) = varint.push(reader.read_u8()?).map_err(invalid)? {
+            break length;
+        }
+    };
+
+    decode::read_utf8(read_bytes(reader, length)?)
+}
+
+fn read_lookup<'b>(reader: &mut dyn Read, lookup: &'b [String]) -> Result<'static, &'b String> {
+    decode::read_lookup(lookup, reader.read_u16::<LittleEndian>()?)
+}
+
+fn read_attr(reader: &mut dyn Read, lookup: &[String]) -> Result<'static, BinElAttr> {
+    let tag = decode::read_tag(reader.read_u8()?)?;
+    Ok(match tag.payload() {
+        Payload::Number(len) => {
+            let mut buf = [0; 4];
+            reader.read_exact(&mut buf[..len])?;
+            tag.decode_number(&buf[..len])
+        }
+        Payload::Lookup => BinElAttr::Text(read_lookup(reader, lookup)?.clone()),
+        Payload::String => BinElAttr::Text(read_string(reader)?),
+        Payload::Rle => {
+            let length = decode::read_rle_len(reader.read_i16::<LittleEndian>()?)?;
+            BinElAttr::Text(decode::decode_rle(&read_bytes(reader, length)?))
+        }
+    })
+}
+
+/// Reads a `BinaryElement` file as a stream of `BinElEvent`s, without building
+/// a tree of `BinEl`s in memory.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::stream::*;
+///
+/// let file = &include_bytes!("../../tests/empty.bin")[..];
+/// let reader = BinElReader::new(file).unwrap();
+///
+/// let names = reader
+///     .filter_map(|event| match event.unwrap() {
+///         BinElEvent::StartElement { name, .. } => Some(name),
+///         BinElEvent::EndElement => None,
+///     })
+///     .collect::<Vec<_>>();
+///
+/// assert_eq!(names[0], "Map");
+/// ```
+#[derive(Debug)]
+pub struct BinElReader<R> {
+    reader: R,
+    package: String,
+    lookup: Vec<String>,
+    remaining: Vec<u16>,
+    started: bool,
+}
+
+impl<R: Read> BinElReader<R> {
+    /// Create a new `BinElReader`, reading the file's header and lookup table.
+    pub fn new(mut reader: R) -> Result<'static, Self> {
+        if read_string(&mut reader)? != "CELESTE MAP" {
+            return Err(invalid("Missing CELESTE MAP header"));
+        }
+
+        let package = read_string(&mut reader)?;
+
+        let length = reader.read_i16::<LittleEndian>()?;
+        let lookup = (0..length.max(0))
+            .map(|_| read_string(&mut reader))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            reader,
+            package,
+            lookup,
+            remaining: Vec::new(),
+            started: false,
+        })
+    }
+
+    /// The file's "package". Usually the name of the file it is stored in.
+    pub fn package(&self) -> &str {
+        &self.package
+    }
+
+    /// The file's string lookup table.
+    pub fn lookup(&self) -> &[String] {
+        &self.lookup
+    }
+
+    /// Get back the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn read_start(&mut self) -> Result<'static, BinElEvent> {
+        let name = read_lookup(&mut self.reader, &self.lookup)?.clone();
+
+        let attr_count = self.reader.read_u8()?;
+        let mut attributes = IndexMap::with_capacity(attr_count as usize);
+        for _ in 0..attr_count {
+            let key = read_lookup(&mut self.reader, &self.lookup)?.clone();
+            let val = read_attr(&mut self.reader, &self.lookup)?;
+            attributes.insert(key, val);
+        }
+
+        let children = self.reader.read_u16::<LittleEndian>()?;
+        self.remaining.push(children);
+
+        Ok(BinElEvent::StartElement {
+            name,
+            attributes,
+            children,
+        })
+    }
+
+    /// Read the next event, or `None` once the root element has ended.
+    pub fn next_event(&mut self) -> Result<'static, Option<BinElEvent>> {
+        if !self.started {
+            self.started = true;
+            return self.read_start().map(Some);
+        }
+
+        match self.remaining.last_mut() {
+            None => Ok(None),
+            Some(0) => {
+                self.remaining.pop();
+                Ok(Some(BinElEvent::EndElement))
+            }
+            Some(remaining) => {
+                *remaining -= 1;
+                self.read_start().map(Some)
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for BinElReader<R> {
+    type Item = Result<'static, BinElEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.next_event();
+        if event.is_err() {
+            // the stream is no longer in a known state
+            self.started = true;
+            self.remaining.clear();
+        }
+        event.transpose()
+    }
+}
+
+/// Writes a `BinaryElement` file from a stream of `BinElEvent`s, without
+/// building a tree of `BinEl`s in memory. As the lookup table comes before any
+/// elements, it must be known upfront, and must contain every element and
+/// attribute name that will be written.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::stream::*;
+/// use indexmap::IndexMap;
+///
+/// let lookup = vec!["Map".to_string()];
+/// let mut writer = BinElWriter::new(Vec::new(), "pkg", lookup).unwrap();
+///
+/// writer
+///     .write_event(&BinElEvent::StartElement {
+///         name: "Map".to_string(),
+///         attributes: IndexMap::new(),
+///         children: 0,
+///     })
+///     .unwrap();
+/// writer.write_event(&BinElEvent::EndElement).unwrap();
+///
+/// let buf = writer.finish().unwrap();
+/// assert_eq!(&buf[..], b"\x0bCELESTE MAP\x03pkg\x01\x00\x03Map\x00\x00\x00\x00\x00");
+/// ```
+#[derive(Debug)]
+pub struct BinElWriter<W> {
+    writer: W,
+    package: String,
+    lookup: Vec<String>,
+    remaining: Vec<u16>,
+    names: Vec<String>,
+    started: bool,
+}
+
+impl<W: Write> BinElWriter<W> {
+    /// Create a new `BinElWriter`. The file's header and lookup table are
+    /// written along with the root element, once the size of the lookup table
+    /// has been checked against the root's path.
+    pub fn new(writer: W, package: &str, lookup: Vec<String>) -> Result<'static, Self> {
+        Ok(Self {
+            writer,
+            package: package.to_string(),
+            lookup,
+            remaining: Vec::new(),
+            names: Vec::new(),
+            started: false,
+        })
+    }
+
+    fn write_header(&mut self) -> Result<'static, ()> {
+        writer::put_string(&mut self.writer, "CELESTE MAP")?;
+        writer::put_string(&mut self.writer, &self.package)?;
+
+        self.writer
+            .write_i16::<LittleEndian>(self.lookup.len() as i16)?;
+        for s in &self.lookup {
+            writer::put_string(&mut self.writer, s)?;
+        }
+
+        Ok(())
+    }
+
+    fn check_limit(
+        &self,
+        name: &str,
+        limit: &'static str,
+        max: usize,
+        found: usize,
+    ) -> Result<'static, ()> {
+        if found <= max {
+            return Ok(());
+        }
+
+        let mut path = self.names.join("/");
+        if !path.is_empty() {
+            path.push('/');
+        }
+        path.push_str(name);
+
+        Err(Error::LimitExceeded {
+            path,
+            limit,
+            max,
+            found,
+        })
+    }
+
+    fn lookup_index(&self, name: &str, kind: &str) -> Result<'static, u16> {
+        match self.lookup.iter().position(|e| *e == name) {
+            Some(p) => Ok(p as u16),
+            None => Err(Error::io(
+                ErrorKind::NotFound,
+                format!("{} name {} is missing in lookup", kind, name),
+            )),
+        }
+    }
+
+    /// Write an event.
+    pub fn write_event(&mut self, event: &BinElEvent) -> Result<'static, ()> {
+        match event {
+            BinElEvent::StartElement {
+                name,
+                attributes,
+                children,
+            } => {
+                self.check_limit(name, "attributes", writer::MAX_ATTRIBUTES, attributes.len())?;
+                self.check_limit(name, "children", writer::MAX_CHILDREN, *children as usize)?;
+
+                match self.remaining.last_mut() {
+                    Some(0) => return Err(invalid("Element has more children than declared")),
+                    Some(remaining) => *remaining -= 1,
+                    None if self.started => return Err(invalid("File has more than one root")),
+                    None => {
+                        self.check_limit(
+                            name,
+                            "unique strings",
+                            writer::MAX_LOOKUP,
+                            self.lookup.len(),
+                        )?;
+                        self.write_header()?;
+                        self.started = true;
+                    }
+                }
+
+                let name_index = self.lookup_index(name, "Element")?;
+                self.writer.write_u16::<LittleEndian>(name_index)?;
+                self.writer.write_u8(attributes.len() as u8)?;
+
+                for (attr, value) in attributes {
+                    let attr_index = self.lookup_index(attr, "Attribute")?;
+                    self.writer.write_u16::<LittleEndian>(attr_index)?;
+                    writer::put_tagged_attr(&mut self.writer, &self.lookup, value, None)?;
+                }
+
+                self.writer.write_u16::<LittleEndian>(*children)?;
+                self.remaining.push(*children);
+                self.names.push(name.clone());
+            }
+            BinElEvent::EndElement => match self.remaining.pop() {
+                Some(0) => {
+                    self.names.pop();
+                }
+                Some(missing) => {
+                    return Err(invalid(format!(
+                        "Element ended with {} children missing",
+                        missing
+                    )))
+                }
+                None => return Err(invalid("EndElement without a matching StartElement")),
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Check that the root element has ended, and get back the underlying
+    /// writer.
+    pub fn finish(self) -> Result<'static, W> {
+        if !self.started || !self.remaining.is_empty() {
+            return Err(invalid("Root element was not completed"));
+        }
+
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip_events() {
+        let file = &include_bytes!("../../tests/empty.bin")[..];
+        let reader = BinElReader::new(file).unwrap();
+        let mut writer =
+            BinElWriter::new(Vec::new(), reader.package(), reader.lookup().to_vec()).unwrap();
+
+        for event in reader {
+            writer.write_event(&event.unwrap()).unwrap();
+        }
+
+        assert_eq!(&writer.finish().unwrap()[..], file);
+    }
+
+    #[test]
+    fn reject_oversized_length() {
+        // a package claiming to be usize::MAX bytes long
+        let mut file = b"\x0bCELESTE MAP".to_vec();
+        file.extend_from_slice(&[0xff; 9]);
+        file.extend_from_slice(b"\x01pkg");
+
+        let err = BinElReader::new(&file[..]).unwrap_err();
+        assert!(err.to_string().ends_with("but only 3 remain"));
+    }
+
+    #[test]
+    fn reject_missing_children() {
+        let lookup = vec!["Map".to_string()];
+        let mut writer = BinElWriter::new(Vec::new(), "pkg", lookup).unwrap();
+
+        writer
+            .write_event(&BinElEvent::StartElement {
+                name: "Map".to_string(),
+                attributes: IndexMap::new(),
+                children: 1,
+            })
+            .unwrap();
+        assert!(writer.write_event(&BinElEvent::EndElement).is_err());
+    }
+
+    #[test]
+    fn reject_large_lookup() {
+        let lookup = (0..=writer::MAX_LOOKUP).map(|i| i.to_string()).collect();
+        let mut writer = BinElWriter::new(Vec::new(), "pkg", lookup).unwrap();
+
+        let err = writer
+            .write_event(&BinElEvent::StartElement {
+                name: "Map".to_string(),
+                attributes: IndexMap::new(),
+                children: 0,
+            })
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`Map` has 32768 unique strings, but the format allows at most 32767"
+        );
+    }
+
+    #[test]
+    fn reject_too_many_attributes() {
+        let lookup = vec!["Map".to_string(), "levels".to_string()];
+        let mut writer = BinElWriter::new(Vec::new(), "pkg", lookup).unwrap();
+
+        writer
+            .write_event(&BinElEvent::StartElement {
+                name: "Map".to_string(),
+                attributes: IndexMap::new(),
+                children: 1,
+            })
+            .unwrap();
+
+        let attributes = (0..=writer::MAX_ATTRIBUTES)
+            .map(|i| (i.to_string(), BinElAttr::Int(0)))
+            .collect();
+        let err = writer
+            .write_event(&BinElEvent::StartElement {
+                name: "levels".to_string(),
+                attributes,
+                children: 0,
+            })
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`Map/levels` has 256 attributes, but the format allows at most 255"
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use super::*;
+use std::collections::HashMap;
+
+/// What to do with an element after a `Visitor` enters or leaves it.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Action {
+    /// Keep the element, visiting its children if it was just entered.
+    Continue,
+    /// Keep the element without visiting its children. The same as `Continue`
+    /// when leaving.
+    Skip,
+    /// Replace the element. The replacement isn't visited.
+    Replace(BinEl),
+    /// Delete the element. Deleting the root has no effect.
+    Delete,
+}
+
+/// Visits a tree of `BinEl`s depth-first through `BinEl::visit`. Both hooks
+/// receive the path to the element's parent, starting from the root. Each
+/// ancestor is indexed among its siblings of the same name, leaving out the
+/// index of the first, as in error paths such as `Map/levels/level[3]`.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::{visit::*, *};
+///
+/// struct RemoveSpikes;
+///
+/// impl Visitor for RemoveSpikes {
+///     fn enter(&mut self, elem: &mut BinEl, path: &[String]) -> Action {
+///         if path.last().map(String::as_str) == Some("entities") && elem.name.starts_with("spikes") {
+///             Action::Delete
+///         } else {
+///             Action::Continue
+///         }
+///     }
+/// }
+///
+/// let mut level = BinEl::build("level")
+///     .child(BinEl::build("entities").child(BinEl::new("spikesUp")).child(BinEl::new("player")))
+///     .finish();
+/// level.visit(&mut RemoveSpikes);
+///
+/// let entities = level.get("entities").next().unwrap();
+/// assert_eq!(entities.children().map(|e| &e.name).collect::<Vec<_>>(), vec!["player"]);
+/// ```
+pub trait Visitor {
+    /// Called before an element's children are visited.
+    fn enter(&mut self, _elem: &mut BinEl, _path: &[String]) -> Action {
+        Action::Continue
+    }
+
+    /// Called after an element's children are visited. Not called if `enter`
+    /// replaced or deleted the element.
+    fn leave(&mut self, _elem: &mut BinEl, _path: &[String]) -> Action {
+        Action::Continue
+    }
+}
+
+struct WalkMut<F>(F);
+
+impl<F: FnMut(&mut BinEl, &[String])> Visitor for WalkMut<F> {
+    fn enter(&mut self, elem: &mut BinEl, path: &[String]) -> Action {
+        (self.0)(elem, path);
+        Action::Continue
+    }
+}
+
+// Names elements by their position among siblings of the same name.
+#[derive(Default)]
+struct Segments(HashMap<String, usize>);
+
+impl Segments {
+    fn next(&mut self, name: &str) -> String {
+        let position = self.0.entry(name.to_string()).or_insert(0);
+        *position += 1;
+        path_segment(name, *position - 1)
+    }
+}
+
+fn visit_element<V: Visitor>(
+    elem: &mut BinEl,
+    segment: String,
+    visitor: &mut V,
+    path: &mut Vec<String>,
+) -> Action {
+    match visitor.enter(elem, path) {
+        Action::Continue => {
+            path.push(segment);
+            let children = std::mem::take(&mut elem.children);
+            let mut segments = Segments::default();
+            for mut child in children {
+                let segment = segments.next(&child.name);
+                match visit_element(&mut child, segment, visitor, path) {
+                    Action::Continue | Action::Skip => elem.children.push(child),
+                    Action::Replace(new) => elem.children.push(new),
+                    Action::Delete => {}
+                }
+            }
+            path.pop();
+        }
+        Action::Skip => {}
+        action => return action,
+    }
+
+    visitor.leave(elem, path)
+}
+
+fn walk_element<'a>(
+    elem: &'a BinEl,
+    segment: String,
+    f: &mut impl FnMut(&'a BinEl, &[String]),
+    path: &mut Vec<String>,
+) {
+    f(elem, path);
+    path.push(segment);
+    let mut segments = Segments::default();
+    for child in elem.children() {
+        walk_element(child, segments.next(&child.name), f, path);
+    }
+    path.pop();
+}
+
+impl BinEl {
+    /// Visit the `BinEl` and all of its descendants depth-first, in document
+    /// order, along with the path to their parent, as with `Visitor`.
+    ///
+    /// # Examples:
+    /// ```
+    /// use celeste::binel::*;
+    ///
+    /// let map = BinEl::build("Map")
+    ///     .child(BinEl::build("levels").child(BinEl::new("level")).child(BinEl::new("level")))
+    ///     .finish();
+    ///
+    /// let mut paths = Vec::new();
+    /// map.walk(|elem, parent| paths.push(format!("{}: {}", parent.join("/"), elem.name)));
+    /// assert_eq!(paths, vec![": Map", "Map: levels", "Map/levels: level", "Map/levels: level"]);
+    /// ```
+    pub fn walk<'a>(&'a self, mut f: impl FnMut(&'a BinEl, &[String])) {
+        walk_element(self, self.name.clone(), &mut f, &mut Vec::new());
+    }
+
+    /// Visit the `BinEl` and all of its descendants depth-first in document
+    /// order, mutable, along with the path to their parent, as with `Visitor`.
+    /// Children added by `f` are visited as well.
+    ///
+    /// # Examples:
+    /// ```
+    /// use celeste::binel::*;
+    ///
+    /// let mut level = BinEl::build("level")
+    ///     .child(BinEl::build("entities").child(BinEl::build("spikesUp").attr("x", 8)))
+    ///     .finish();
+    ///
+    /// level.walk_mut(|elem, _| {
+    ///     if let Some(BinElAttr::Int(x)) = elem.attributes.get_mut("x") {
+    ///         *x *= 2;
+    ///     }
+    /// });
+    /// assert_eq!(level.select("//spikesUp[@x=16]").unwrap().count(), 1);
+    /// ```
+    pub fn walk_mut(&mut self, f: impl FnMut(&mut BinEl, &[String])) {
+        self.visit(&mut WalkMut(f));
+    }
+
+    /// Visit the `BinEl` and all of its descendants depth-first with a
+    /// `Visitor`, in document order.
+    pub fn visit<V: Visitor>(&mut self, visitor: &mut V) {
+        if let Action::Replace(new) =
+            visit_element(self, self.name.clone(), visitor, &mut Vec::new())
+        {
+            *self = new;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn level() -> BinEl {
+        BinEl::build("level")
+            .child(
+                BinEl::build("entities")
+                    .child(BinEl::build("spikesUp").child(BinEl::new("node")))
+                    .child(BinEl::new("player")),
+            )
+            .child(BinEl::new("triggers"))
+            .finish()
+    }
+
+    #[derive(Default)]
+    struct Log {
+        events: Vec<String>,
+        action: Option<(&'static str, Action)>,
+    }
+
+    impl Visitor for Log {
+        fn enter(&mut self, elem: &mut BinEl, path: &[String]) -> Action {
+            self.events
+                .push(format!("enter {} {}", path.join("/"), elem.name));
+            match &self.action {
+                Some((name, action)) if elem.name == *name => action.clone(),
+                _ => Action::Continue,
+            }
+        }
+
+        fn leave(&mut self, elem: &mut BinEl, _path: &[String]) -> Action {
+            self.events.push(format!("leave {}", elem.name));
+            Action::Continue
+        }
+    }
+
+    #[test]
+    fn visit_order() {
+        let mut log = Log::default();
+        level().visit(&mut log);
+        assert_eq!(
+            log.events,
+            vec![
+                "enter  level",
+                "enter level entities",
+                "enter level/entities spikesUp",
+                "enter level/entities/spikesUp node",
+                "leave node",
+                "leave spikesUp",
+                "enter level/entities player",
+                "leave player",
+                "leave entities",
+                "enter level triggers",
+                "leave triggers",
+                "leave level",
+            ]
+        );
+    }
+
+    #[test]
+    fn visit_actions() {
+        let mut log = Log {
+            action: Some(("spikesUp", Action::Skip)),
+            ..Log::default()
+        };
+        let mut skipped = level();
+        skipped.visit(&mut log);
+        assert_eq!(skipped, level());
+        assert!(!log.events.iter().any(|e| e.ends_with("node")));
+
+        let mut log = Log {
+            action: Some(("entities", Action::Replace(BinEl::new("fgdecals")))),
+            ..Log::default()
+        };
+        let mut replaced = level();
+        replaced.visit(&mut log);
+        let names: Vec<_> = replaced.children().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["fgdecals", "triggers"]);
+        assert!(!log.events.contains(&"leave entities".to_string()));
+
+        let mut log = Log {
+            action: Some(("level", Action::Delete)),
+            ..Log::default()
+        };
+        let mut root = level();
+        root.visit(&mut log);
+        assert_eq!(root, level());
+    }
+
+    #[test]
+    fn walk_paths_match() {
+        let mut level = level();
+        let entities = level.children_mut().next().unwrap();
+        entities.insert(BinEl::build("spikesUp").child(BinEl::new("node")).finish());
+
+        let mut paths = Vec::new();
+        level.walk(|elem, path| paths.push(format!("{} {}", path.join("/"), elem.name)));
+        assert_eq!(paths[5], "level/entities spikesUp");
+        assert_eq!(paths[6], "level/entities/spikesUp[1] node");
+
+        let mut paths_mut = Vec::new();
+        level.walk_mut(|elem, path| paths_mut.push(format!("{} {}", path.join("/"), elem.name)));
+        assert_eq!(paths_mut, paths);
+    }
+}
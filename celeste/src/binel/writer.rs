@@ -1,7 +1,9 @@
 use super::*;
 use byteorder::{LittleEndian, WriteBytesExt};
 use celeste::{Error, Result};
+use hashbrown::{HashMap, HashSet};
 use itertools::Itertools;
+use std::convert::TryFrom;
 use std::io::{prelude::*, ErrorKind};
 
 /// Write a string using a varint for the length.
@@ -38,10 +40,10 @@ pub fn put_tagged_bool(writer: &mut dyn Write, val: bool) -> Result<'static, ()>
 /// Write an i32 as either a u8 (tagged with 0x01), i16 (tagged with 0x02), or
 /// i32 (tagged with 0x03).
 pub fn put_tagged_int(writer: &mut dyn Write, val: i32) -> Result<'static, ()> {
-    if val >= u8::min_value().into() && val <= u8::max_value().into() {
+    if val >= u8::MIN.into() && val <= u8::MAX.into() {
         writer.write_u8(0x01)?;
         writer.write_u8(val as u8)?;
-    } else if val >= i16::min_value().into() && val <= i16::max_value().into() {
+    } else if val >= i16::MIN.into() && val <= i16::MAX.into() {
         writer.write_u8(0x02)?;
         writer.write_i16::<LittleEndian>(val as i16)?;
     } else {
@@ -60,47 +62,204 @@ pub fn put_tagged_f32(writer: &mut dyn Write, val: f32) -> Result<'static, ()> {
     Ok(())
 }
 
-/// Encode a string in Celeste's RLE format. Allocates two bytes on the heap due
-/// to a current limitation of iterators.
-pub fn encode_rle_string(string: &str) -> Vec<u8> {
-    string
-        .bytes()
-        .group_by(|e| *e)
-        .into_iter()
-        .flat_map(|(ch, run)| vec![run.count() as u8, ch])
-        .collect() // rust#25725
+/// Encode a string in Celeste's RLE format, which stores each character as a
+/// single Latin-1 byte. Runs longer than 255 characters are split. Returns
+/// `None` if the string has characters outside of Latin-1, which can't be
+/// stored.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::writer::encode_rle_string;
+///
+/// assert_eq!(encode_rle_string("aaé").unwrap(), b"\x02a\x01\xe9");
+/// assert_eq!(encode_rle_string(&"0".repeat(300)).unwrap(), b"\xff0\x2d0");
+/// assert_eq!(encode_rle_string("€"), None);
+/// ```
+pub fn encode_rle_string(string: &str) -> Option<Vec<u8>> {
+    let mut rle = Vec::new();
+
+    for (ch, run) in string.chars().group_by(|e| *e).into_iter() {
+        let byte = u8::try_from(u32::from(ch)).ok()?;
+        let mut count = run.count();
+        while count > 0 {
+            let part = count.min(u8::MAX as usize);
+            rle.push(part as u8);
+            rle.push(byte);
+            count -= part;
+        }
+    }
+
+    Some(rle)
 }
 
 /// Write a string either using a lookup (stored as u16, tagged with 0x05),
 /// Celeste's RLE format (tagged with 0x07), or using a varint (tagged with
-/// 0x06).
+/// 0x06). Strings that can't be stored with RLE always use a varint.
 pub fn put_tagged_str(
     mut writer: &mut dyn Write,
     lookup: &[String],
     val: &str,
 ) -> Result<'static, ()> {
-    if let Some(index) = lookup.iter().position(|e| *e == val) {
+    let index = lookup.iter().position(|e| *e == val);
+    if let Some(index) = index.filter(|&i| i <= MAX_LOOKUP) {
         writer.write_u8(0x05)?;
         writer.write_u16::<LittleEndian>(index as u16)?;
     } else {
-        let rle = encode_rle_string(val);
+        match encode_rle_string(val) {
+            Some(rle) if rle.len() < val.len() && rle.len() <= i16::MAX as usize => {
+                writer.write_u8(0x07)?;
+                writer.write_i16::<LittleEndian>(rle.len() as i16)?;
+                writer.write_all(&rle)?;
+            }
+            _ => {
+                writer.write_u8(0x06)?;
+                put_string(&mut writer, val)?;
+            }
+        }
+    }
 
-        if rle.len() < val.len() && rle.len() <= i16::max_value() as usize {
-            writer.write_u8(0x07)?;
-            writer.write_i16::<LittleEndian>(rle.len() as i16)?;
-            writer.write_all(&rle)?;
-        } else {
+    Ok(())
+}
+
+/// Write a `BinElAttr` using the tag it was originally stored with, if that tag
+/// can still represent the value. Otherwise, picks an encoding the same way as
+/// `put_tagged_int` and `put_tagged_str`.
+pub fn put_tagged_attr(
+    mut writer: &mut dyn Write,
+    lookup: &[String],
+    val: &BinElAttr,
+    tag: Option<BinElAttrTag>,
+) -> Result<'static, ()> {
+    match (val, tag) {
+        (BinElAttr::Int(val), Some(BinElAttrTag::U8)) if u8::try_from(*val).is_ok() => {
+            writer.write_u8(0x01)?;
+            writer.write_u8(*val as u8)?;
+        }
+        (BinElAttr::Int(val), Some(BinElAttrTag::I16)) if i16::try_from(*val).is_ok() => {
+            writer.write_u8(0x02)?;
+            writer.write_i16::<LittleEndian>(*val as i16)?;
+        }
+        (BinElAttr::Int(val), Some(BinElAttrTag::I32)) => {
+            writer.write_u8(0x03)?;
+            writer.write_i32::<LittleEndian>(*val)?;
+        }
+        (BinElAttr::Text(val), Some(BinElAttrTag::String)) => {
             writer.write_u8(0x06)?;
             put_string(&mut writer, val)?;
         }
+        (BinElAttr::Text(val), Some(BinElAttrTag::Rle)) => match encode_rle_string(val) {
+            Some(rle) if rle.len() <= i16::MAX as usize => {
+                writer.write_u8(0x07)?;
+                writer.write_i16::<LittleEndian>(rle.len() as i16)?;
+                writer.write_all(&rle)?;
+            }
+            _ => put_tagged_str(&mut writer, lookup, val)?,
+        },
+        (BinElAttr::Bool(val), _) => put_tagged_bool(&mut writer, *val)?,
+        (BinElAttr::Int(val), _) => put_tagged_int(&mut writer, *val)?,
+        (BinElAttr::Float(val), _) => put_tagged_f32(&mut writer, *val)?,
+        (BinElAttr::Text(val), _) => put_tagged_str(&mut writer, lookup, val)?,
     }
 
     Ok(())
 }
 
-/// Write a `BinEl` using an existing lookup table for element and attribute
-/// named.
-pub fn put_element(
+/// The most attributes a `BinEl` can have, as the count is stored as a u8.
+pub const MAX_ATTRIBUTES: usize = u8::MAX as usize;
+
+/// The most children a `BinEl` can have, as Celeste reads the count as an i16.
+pub const MAX_CHILDREN: usize = i16::MAX as usize;
+
+/// The most strings a lookup table can have, as Celeste reads both its length
+/// and indices into it as i16s.
+pub const MAX_LOOKUP: usize = i16::MAX as usize;
+
+fn limit_error(path: String, limit: &'static str, max: usize, found: usize) -> Error<'static> {
+    Error::LimitExceeded {
+        path,
+        limit,
+        max,
+        found,
+    }
+}
+
+fn child_segment(parent: &BinEl, index: usize) -> String {
+    let child = &parent.children[index];
+    let position = parent.children[..index]
+        .iter()
+        .filter(|e| e.name == child.name)
+        .count();
+    path_segment(&child.name, position)
+}
+
+// Errors have paths relative to `elem`, so the caller can prefix them.
+fn check_element(elem: &BinEl) -> Result<'static, ()> {
+    if elem.attributes.len() > MAX_ATTRIBUTES {
+        return Err(limit_error(
+            String::new(),
+            "attributes",
+            MAX_ATTRIBUTES,
+            elem.attributes.len(),
+        ));
+    }
+
+    if elem.children.len() > MAX_CHILDREN {
+        return Err(limit_error(
+            String::new(),
+            "children",
+            MAX_CHILDREN,
+            elem.children.len(),
+        ));
+    }
+
+    for index in 0..elem.children.len() {
+        check_element(&elem.children[index])
+            .map_err(|err| err.in_path(&child_segment(elem, index)))?;
+    }
+
+    Ok(())
+}
+
+/// Check that a `BinEl` can be written without exceeding any of the limits of
+/// the binary format. This is done automatically by `put_element` and
+/// `put_file` before anything is written.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::{writer::*, *};
+///
+/// let mut entities = BinEl::new("entities");
+/// for i in 0..=MAX_ATTRIBUTES {
+///     entities.attributes.insert(i.to_string(), BinElAttr::Int(0));
+/// }
+///
+/// let mut level = BinEl::new("level");
+/// level.insert(entities);
+///
+/// let err = check_limits(&level).unwrap_err();
+/// assert_eq!(
+///     err.to_string(),
+///     "`level/entities` has 256 attributes, but the format allows at most 255"
+/// );
+/// ```
+pub fn check_limits(elem: &BinEl) -> Result<'static, ()> {
+    check_element(elem).map_err(|err| err.in_path(&elem.name))
+}
+
+fn check_lookup(lookup: &[String], root: &BinEl) -> Result<'static, ()> {
+    if lookup.len() > MAX_LOOKUP {
+        return Err(limit_error(
+            root.name.clone(),
+            "unique strings",
+            MAX_LOOKUP,
+            lookup.len(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn write_element(
     mut writer: &mut dyn Write,
     lookup: &[String],
     elem: &BinEl,
@@ -129,73 +288,133 @@ pub fn put_element(
             }
         };
         writer.write_u16::<LittleEndian>(attr_index as u16)?;
-        match value {
-            BinElAttr::Bool(val) => put_tagged_bool(&mut writer, *val)?,
-            BinElAttr::Int(val) => put_tagged_int(&mut writer, *val)?,
-            BinElAttr::Float(val) => put_tagged_f32(&mut writer, *val)?,
-            BinElAttr::Text(val) => put_tagged_str(&mut writer, lookup, &val)?,
-        }
+        put_tagged_attr(&mut writer, lookup, value, elem.tags.get(attr).copied())?;
     }
 
     writer.write_u16::<LittleEndian>(elem.children().count() as u16)?;
     for child in elem.children() {
-        put_element(&mut writer, lookup, child)?;
+        write_element(&mut writer, lookup, child)?;
     }
 
     Ok(())
 }
 
-fn gen_lookup_keys(binel: &BinEl, mut seen: &mut HashMap<String, usize>) {
-    seen.insert(
-        binel.name.clone(),
-        seen.get(binel.name.as_str()).unwrap_or(&0) + 1,
-    );
+/// Write a `BinEl` using an existing lookup table for element and attribute
+/// named. Returns `Error::LimitExceeded` without writing anything if the
+/// `BinEl` or lookup table exceed the limits of the format.
+pub fn put_element(writer: &mut dyn Write, lookup: &[String], elem: &BinEl) -> Result<'static, ()> {
+    check_lookup(lookup, elem)?;
+    check_limits(elem)?;
+    write_element(writer, lookup, elem)
+}
+
+fn gen_lookup_keys(binel: &BinEl, seen: &mut HashMap<String, usize>) {
+    binel.walk(|elem, _| {
+        *seen.entry(elem.name.clone()).or_insert(0) += 1;
 
-    for (k, v) in &binel.attributes {
-        seen.insert(k.clone(), seen.get(k.as_str()).unwrap_or(&0) + 1);
-        if k != "innerText" {
-            if let BinElAttr::Text(text) = v {
-                seen.insert(text.clone(), seen.get(text.as_str()).unwrap_or(&0) + 1);
+        for (k, v) in &elem.attributes {
+            *seen.entry(k.clone()).or_insert(0) += 1;
+            if k != "innerText" {
+                if let BinElAttr::Text(text) = v {
+                    *seen.entry(text.clone()).or_insert(0) += 1;
+                }
             }
         }
-    }
-
-    for child in binel.children() {
-        gen_lookup_keys(child, &mut seen);
-    }
+    });
 }
 
 /// Generate a string lookup using the attributes and element names in a
-/// `BinEl`.
+/// `BinEl`. The most common strings come first, with ties sorted lexically, so
+/// the same `BinEl` always generates the same lookup.
 pub fn gen_lookup(binel: &BinEl) -> Vec<String> {
     let mut seen = HashMap::new();
     gen_lookup_keys(binel, &mut seen);
     let mut vec = seen.into_iter().collect::<Vec<(String, usize)>>();
-    vec.sort_unstable_by_key(|e| e.1);
-    vec.into_iter().rev().map(|e| e.0).collect()
+    vec.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    vec.into_iter().map(|e| e.0).collect()
+}
+
+/// Extend an existing string lookup with any element and attribute names in a
+/// `BinEl` that it is missing. Existing entries keep their indices.
+pub fn extend_lookup(lookup: &[String], binel: &BinEl) -> Vec<String> {
+    let mut extended = lookup.to_vec();
+    let mut known: HashSet<&str> = lookup.iter().map(String::as_str).collect();
+    binel.walk(|elem, _| {
+        for name in std::iter::once(&elem.name).chain(elem.attributes.keys()) {
+            if known.insert(name) {
+                extended.push(name.clone());
+            }
+        }
+    });
+    extended
+}
+
+/// Write a `BinFile`. If the file has a lookup table, it is reused (and
+/// extended if necessary), otherwise a new one is generated. Tested solely in
+/// integration tests due to complexity.
+pub fn put_file(writer: &mut dyn Write, bin: &BinFile) -> Result<'static, ()> {
+    match &bin.lookup {
+        Some(lookup) => put_file_with_lookup(writer, bin, lookup),
+        None => write_file(writer, bin, gen_lookup(&bin.root)),
+    }
 }
 
-/// Write a `BinFile`. Tested solely in integration tests due to complexity.
-pub fn put_file(mut writer: &mut dyn Write, bin: &BinFile) -> Result<'static, ()> {
+/// Write a `BinFile` using a caller-supplied lookup table, such as the one of
+/// the file it was originally read from, ignoring `BinFile::lookup`. The
+/// table is extended if necessary, but existing entries keep their indices, so
+/// writing the same `BinFile` with the same table always gives the same bytes.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::*;
+///
+/// let map_bytes = include_bytes!("../../tests/empty.bin");
+/// let original = parser::parse_file_lossless(map_bytes).unwrap();
+/// let mut bin = parser::parse_file(map_bytes).unwrap();
+/// bin.root.attributes.insert("Version".to_string(), BinElAttr::Int(2));
+///
+/// let mut buf = Vec::new();
+/// writer::put_file_with_lookup(&mut buf, &bin, original.lookup.as_ref().unwrap()).unwrap();
+///
+/// let written = parser::parse_file_lossless(&buf).unwrap();
+/// let lookup = written.lookup.unwrap();
+/// assert!(lookup.starts_with(original.lookup.as_ref().unwrap()));
+/// assert_eq!(lookup.last().unwrap(), "Version");
+/// ```
+pub fn put_file_with_lookup(
+    writer: &mut dyn Write,
+    bin: &BinFile,
+    lookup: &[String],
+) -> Result<'static, ()> {
+    write_file(writer, bin, extend_lookup(lookup, &bin.root))
+}
+
+fn write_file(
+    mut writer: &mut dyn Write,
+    bin: &BinFile,
+    lookup: Vec<String>,
+) -> Result<'static, ()> {
+    check_lookup(&lookup, &bin.root)?;
+    check_limits(&bin.root)?;
+
     put_string(&mut writer, "CELESTE MAP")?;
 
     put_string(&mut writer, &bin.package)?;
 
-    let lookup = gen_lookup(&bin.root);
-
     writer.write_i16::<LittleEndian>(lookup.len() as i16)?;
 
     for s in &lookup {
-        put_string(&mut writer, &s)?;
+        put_string(&mut writer, s)?;
     }
 
-    put_element(&mut writer, &lookup, &bin.root)?;
+    write_element(&mut writer, &lookup, &bin.root)?;
 
     Ok(())
 }
 
 #[cfg(test)]
 mod test {
+    use super::*;
     use std::io::Cursor;
 
     #[test]
@@ -206,4 +425,96 @@ mod test {
 
         assert_eq!(&buf.get_ref()[..], b"\x0bCELESTE MAP");
     }
+
+    #[test]
+    fn put_tagged_attr_keeps_tag() {
+        let mut buf = Cursor::new(vec![]);
+        put_tagged_attr(&mut buf, &[], &BinElAttr::Int(5), Some(BinElAttrTag::I32)).unwrap();
+        assert_eq!(&buf.get_ref()[..], b"\x03\x05\x00\x00\x00");
+
+        let mut buf = Cursor::new(vec![]);
+        put_tagged_attr(&mut buf, &[], &BinElAttr::Int(-1), Some(BinElAttrTag::U8)).unwrap();
+        assert_eq!(&buf.get_ref()[..], b"\x02\xff\xff");
+
+        let lookup = ["a".to_string()];
+        let text = BinElAttr::Text("a".to_string());
+        let mut buf = Cursor::new(vec![]);
+        put_tagged_attr(&mut buf, &lookup, &text, Some(BinElAttrTag::String)).unwrap();
+        assert_eq!(&buf.get_ref()[..], b"\x06\x01a");
+    }
+
+    #[test]
+    fn put_tagged_str_non_latin1() {
+        let mut buf = Cursor::new(vec![]);
+        put_tagged_str(&mut buf, &[], "€€€€€€").unwrap();
+        assert_eq!(buf.get_ref()[0], 0x06);
+
+        let mut buf = Cursor::new(vec![]);
+        put_tagged_str(&mut buf, &[], "éééééé").unwrap();
+        assert_eq!(&buf.get_ref()[..], b"\x07\x02\x00\x06\xe9");
+    }
+
+    #[test]
+    fn gen_lookup_breaks_ties() {
+        let level = BinEl::build("level")
+            .attr("name", "b")
+            .child(BinEl::build("entity").attr("name", "a"))
+            .child(BinEl::build("entity").attr("x", 0))
+            .finish();
+
+        assert_eq!(
+            gen_lookup(&level),
+            vec!["entity", "name", "a", "b", "level", "x"]
+        );
+    }
+
+    #[test]
+    fn reject_too_many_children() {
+        let mut levels = BinEl::new("levels");
+        for i in 0..2 {
+            let mut level = BinEl::new("level");
+            if i == 1 {
+                for _ in 0..=MAX_CHILDREN {
+                    level.insert(BinEl::new("entity"));
+                }
+            }
+            levels.insert(level);
+        }
+
+        let mut root = BinEl::new("Map");
+        root.insert(levels);
+
+        let bin = BinFile {
+            package: "pkg".to_string(),
+            root,
+            lookup: None,
+        };
+
+        let mut buf = Vec::new();
+        match put_file(&mut buf, &bin) {
+            Err(Error::LimitExceeded {
+                path, limit, found, ..
+            }) => {
+                assert_eq!(path, "Map/levels/level[1]");
+                assert_eq!(limit, "children");
+                assert_eq!(found, MAX_CHILDREN + 1);
+            }
+            other => panic!("expected LimitExceeded, got {:?}", other),
+        }
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn reject_large_lookup() {
+        let mut root = BinEl::new("Map");
+        for i in 0..MAX_LOOKUP {
+            root.insert(BinEl::new(&i.to_string()));
+        }
+
+        let lookup = gen_lookup(&root);
+        assert!(matches!(
+            put_element(&mut Vec::new(), &lookup, &root),
+            Err(Error::LimitExceeded { .. })
+        ));
+    }
 }
@@ -0,0 +1,425 @@
+use super::*;
+use crate::{Error, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::io::{ErrorKind, Write};
+
+/// The root attribute the file's package is stored in.
+pub const PACKAGE_ATTR: &str = "_package";
+
+/// The attribute listing (space-separated) the names of text attributes that
+/// would otherwise be read back as a bool, int or float.
+pub const TEXT_ATTR: &str = "_text";
+
+fn escape(text: &str, attr: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' if attr => escaped.push_str("&quot;"),
+            '\n' if attr => escaped.push_str("&#xA;"),
+            '\t' if attr => escaped.push_str("&#x9;"),
+            '\r' => escaped.push_str("&#xD;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Guess the type of an attribute from its XML representation, the same way
+/// Celeste does.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::{xml::infer_attr, BinElAttr};
+///
+/// assert_eq!(infer_attr("True"), BinElAttr::Bool(true));
+/// assert_eq!(infer_attr("-3"), BinElAttr::Int(-3));
+/// assert_eq!(infer_attr("0.5"), BinElAttr::Float(0.5));
+/// assert_eq!(infer_attr("lvl_1"), BinElAttr::Text("lvl_1".to_string()));
+/// ```
+pub fn infer_attr(value: &str) -> BinElAttr {
+    if value.eq_ignore_ascii_case("true") {
+        BinElAttr::Bool(true)
+    } else if value.eq_ignore_ascii_case("false") {
+        BinElAttr::Bool(false)
+    } else if let Ok(int) = value.parse() {
+        BinElAttr::Int(int)
+    } else if let Ok(float) = value.parse() {
+        BinElAttr::Float(float)
+    } else {
+        BinElAttr::Text(value.to_string())
+    }
+}
+
+fn attr_to_string(attr: &BinElAttr) -> String {
+    match attr {
+        BinElAttr::Bool(val) => val.to_string(),
+        BinElAttr::Int(val) => val.to_string(),
+        // Debug always includes a decimal point or exponent, so floats are
+        // never read back as ints.
+        BinElAttr::Float(val) => format!("{:?}", val),
+        BinElAttr::Text(val) => val.clone(),
+    }
+}
+
+// Only non-blank text stored last is written as content, as it would
+// otherwise change the attribute order or be mistaken for formatting.
+fn inline_text(elem: &BinEl) -> Option<&String> {
+    match elem.attributes.iter().next_back() {
+        Some((key, BinElAttr::Text(text))) if key == "innerText" && !text.trim().is_empty() => {
+            Some(text)
+        }
+        _ => None,
+    }
+}
+
+fn put_element(
+    writer: &mut dyn Write,
+    elem: &BinEl,
+    package: Option<&str>,
+    depth: Option<usize>,
+) -> Result<'static, ()> {
+    let text = inline_text(elem);
+
+    if let Some(depth) = depth {
+        write!(writer, "{:width$}", "", width = depth * 2)?;
+    }
+    write!(writer, "<{}", elem.name)?;
+
+    if let Some(package) = package {
+        write!(writer, " {}=\"{}\"", PACKAGE_ATTR, escape(package, true))?;
+    }
+
+    let mut text_attrs = Vec::new();
+    for (key, value) in &elem.attributes {
+        if key == "innerText" && text.is_some() {
+            continue;
+        }
+        // these would be consumed when read back
+        if key == TEXT_ATTR || (package.is_some() && key == PACKAGE_ATTR) {
+            return Err(Error::io(
+                ErrorKind::InvalidInput,
+                format!(
+                    "`{}` has an attribute named `{}`, which is reserved in XML",
+                    elem.name, key
+                ),
+            ));
+        }
+
+        let string = attr_to_string(value);
+        if let BinElAttr::Text(_) = value {
+            if infer_attr(&string) != *value {
+                text_attrs.push(key.as_str());
+            }
+        }
+        write!(writer, " {}=\"{}\"", key, escape(&string, true))?;
+    }
+
+    if !text_attrs.is_empty() {
+        write!(writer, " {}=\"{}\"", TEXT_ATTR, text_attrs.join(" "))?;
+    }
+
+    if text.is_none() && elem.children().next().is_none() {
+        write!(writer, "/>")?;
+    } else {
+        write!(writer, ">")?;
+
+        // whitespace would become part of the text, so elements with text are
+        // written without any formatting
+        let child_depth = match text {
+            Some(text) => {
+                write!(writer, "{}", escape(text, false))?;
+                None
+            }
+            None => {
+                if depth.is_some() {
+                    writeln!(writer)?;
+                }
+                depth.map(|depth| depth + 1)
+            }
+        };
+
+        for child in elem.children() {
+            put_element(writer, child, None, child_depth)?;
+        }
+
+        if let (Some(depth), None) = (depth, text) {
+            write!(writer, "{:width$}", "", width = depth * 2)?;
+        }
+        write!(writer, "</{}>", elem.name)?;
+    }
+
+    if depth.is_some() {
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+/// Write a `BinFile` as XML. Attributes are written so that their types can be
+/// inferred when read back, with text attributes that would be misread listed
+/// in `TEXT_ATTR`. Non-blank `innerText` is written as the element's content,
+/// and the package is stored in `PACKAGE_ATTR` on the root element. Elements
+/// with attributes of those names are rejected, as they couldn't be read back.
+pub fn write_file(writer: &mut dyn Write, bin: &BinFile) -> Result<'static, ()> {
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"utf-8\"?>")?;
+    put_element(writer, &bin.root, Some(&bin.package), Some(0))
+}
+
+struct Partial {
+    elem: BinEl,
+    text: String,
+    has_text: bool,
+}
+
+pub(crate) fn xml_error<B: std::io::BufRead>(
+    reader: &Reader<B>,
+    message: impl ToString,
+) -> Error<'static> {
+    Error::ParseXml {
+        position: reader.buffer_position(),
+        message: message.to_string(),
+    }
+}
+
+fn start_element<B: std::io::BufRead>(
+    reader: &Reader<B>,
+    start: &BytesStart,
+) -> Result<'static, BinEl> {
+    let name = reader
+        .decode(start.name())
+        .map_err(|err| xml_error(reader, err))?;
+    let mut elem = BinEl::new(name);
+
+    let mut attrs = Vec::new();
+    let mut text_attrs = String::new();
+    for attr in start.attributes() {
+        let attr = attr.map_err(|err| xml_error(reader, err))?;
+        let key = reader
+            .decode(attr.key)
+            .map_err(|err| xml_error(reader, err))?
+            .to_string();
+        let value = attr
+            .unescape_and_decode_value(reader)
+            .map_err(|err| xml_error(reader, err))?;
+
+        if key == TEXT_ATTR {
+            text_attrs = value;
+        } else {
+            attrs.push((key, value));
+        }
+    }
+
+    let text_attrs: Vec<_> = text_attrs.split_whitespace().collect();
+    for (key, value) in attrs {
+        let attr = if text_attrs.contains(&key.as_str()) {
+            BinElAttr::Text(value)
+        } else {
+            infer_attr(&value)
+        };
+        elem.attributes.insert(key, attr);
+    }
+
+    Ok(elem)
+}
+
+fn finish_element(partial: Partial) -> BinEl {
+    let mut elem = partial.elem;
+    if partial.has_text && !elem.attributes.contains_key("innerText") {
+        elem.set_text(&partial.text);
+    }
+    elem
+}
+
+/// Read a `BinFile` from XML written by `write_file`, or by Celeste's own
+/// tools. Attribute types are inferred the same way Celeste does, apart from
+/// attributes listed in `TEXT_ATTR`. The package is read from `PACKAGE_ATTR`
+/// on the root element, and is left empty if it is missing.
+pub fn read_file(xml: &str) -> Result<'static, BinFile> {
+    let mut reader = Reader::from_str(xml);
+    let mut buf = Vec::new();
+    let mut stack: Vec<Partial> = Vec::new();
+    let mut root = None;
+
+    loop {
+        let (elem, closed) = match reader.read_event(&mut buf) {
+            Ok(Event::Start(start)) => (Some(start_element(&reader, &start)?), false),
+            Ok(Event::Empty(start)) => (Some(start_element(&reader, &start)?), true),
+            Ok(Event::End(_)) => (None, true),
+            Ok(Event::Text(text)) => {
+                let text = text
+                    .unescape_and_decode(&reader)
+                    .map_err(|err| xml_error(&reader, err))?;
+                if let Some(partial) = stack.last_mut() {
+                    partial.has_text |= !text.trim().is_empty();
+                    partial.text.push_str(&text);
+                }
+                (None, false)
+            }
+            Ok(Event::CData(text)) => {
+                let text = reader
+                    .decode(&text)
+                    .map_err(|err| xml_error(&reader, err))?
+                    .to_string();
+                if let Some(partial) = stack.last_mut() {
+                    partial.has_text = true;
+                    partial.text.push_str(&text);
+                }
+                (None, false)
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => (None, false),
+            Err(err) => return Err(xml_error(&reader, err)),
+        };
+
+        if let Some(elem) = elem {
+            if root.is_some() {
+                return Err(xml_error(&reader, "File has more than one root"));
+            }
+            stack.push(Partial {
+                elem,
+                text: String::new(),
+                has_text: false,
+            });
+        }
+
+        if closed {
+            let elem = match stack.pop() {
+                Some(partial) => finish_element(partial),
+                None => return Err(xml_error(&reader, "Unexpected closing tag")),
+            };
+            match stack.last_mut() {
+                Some(parent) => parent.elem.insert(elem),
+                None => root = Some(elem),
+            }
+        }
+
+        buf.clear();
+    }
+
+    let mut root = match root {
+        Some(root) if stack.is_empty() => root,
+        _ => return Err(xml_error(&reader, "Root element was not completed")),
+    };
+
+    let package = match root.attributes.shift_remove(PACKAGE_ATTR) {
+        Some(BinElAttr::Text(package)) => package,
+        Some(other) => attr_to_string(&other),
+        None => String::new(),
+    };
+
+    Ok(BinFile {
+        package,
+        root,
+        lookup: None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Error;
+
+    fn roundtrip(bin: &BinFile) -> BinFile {
+        let mut buf = Vec::new();
+        write_file(&mut buf, bin).unwrap();
+        read_file(std::str::from_utf8(&buf).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn roundtrip_map() {
+        let map_bytes = include_bytes!("../../tests/empty.bin");
+        let bin = parser::take_file::<Error>(map_bytes).unwrap().1;
+        assert_eq!(roundtrip(&bin), bin);
+    }
+
+    #[test]
+    fn roundtrip_types() {
+        let mut root = BinEl::new("Map");
+        let attrs = vec![
+            ("bool", BinElAttr::Bool(true)),
+            ("int", BinElAttr::Int(-5)),
+            ("float", BinElAttr::Float(1.0)),
+            (
+                "text",
+                BinElAttr::Text("hi <there> & \"you\"\n".to_string()),
+            ),
+            ("intText", BinElAttr::Text("5".to_string())),
+            ("boolText", BinElAttr::Text("False".to_string())),
+            ("floatText", BinElAttr::Text("+1.50".to_string())),
+            ("emptyText", BinElAttr::Text("".to_string())),
+        ];
+        for (k, v) in attrs {
+            root.attributes.insert(k.to_string(), v);
+        }
+
+        let mut solids = BinEl::new("solids");
+        solids.set_text("\n00\n11 ");
+        root.insert(solids);
+
+        let mut bg = BinEl::new("bg");
+        bg.set_text(" ");
+        root.insert(bg);
+
+        let bin = BinFile {
+            package: "pkg".to_string(),
+            root,
+            lookup: None,
+        };
+        assert_eq!(roundtrip(&bin), bin);
+    }
+
+    #[test]
+    fn reject_reserved_attrs() {
+        let mut root = BinEl::new("Map");
+        root.attributes
+            .insert(PACKAGE_ATTR.to_string(), BinElAttr::Text("pkg".to_string()));
+        let mut bin = BinFile {
+            package: String::new(),
+            root,
+            lookup: None,
+        };
+        let err = write_file(&mut Vec::new(), &bin).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("`Map` has an attribute named `_package`"));
+
+        // `_package` is only read from the root
+        let mut level = BinEl::new("level");
+        level
+            .attributes
+            .insert(PACKAGE_ATTR.to_string(), BinElAttr::Text("pkg".to_string()));
+        bin.root = BinEl::new("Map");
+        bin.root.insert(level);
+        assert_eq!(roundtrip(&bin), bin);
+
+        bin.root
+            .attributes
+            .insert(TEXT_ATTR.to_string(), BinElAttr::Text("".to_string()));
+        assert!(write_file(&mut Vec::new(), &bin).is_err());
+    }
+
+    #[test]
+    fn read_celeste_xml() {
+        let xml = r#"<Map _package="1-ForsakenCity"><levels>
+            <level name="a-00" x="0" dark="False"><solids>0110</solids></level>
+        </levels></Map>"#;
+        let bin = read_file(xml).unwrap();
+        assert_eq!(bin.package, "1-ForsakenCity");
+
+        let level = bin
+            .root
+            .get("levels")
+            .next()
+            .unwrap()
+            .get("level")
+            .next()
+            .unwrap();
+        assert_eq!(level.attributes.get("x"), Some(&BinElAttr::Int(0)));
+        assert_eq!(level.attributes.get("dark"), Some(&BinElAttr::Bool(false)));
+        assert_eq!(level.get("solids").next().unwrap().text().unwrap(), "0110");
+    }
+}
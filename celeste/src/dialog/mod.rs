@@ -21,8 +21,8 @@ pub struct Dialog<'a>(pub DialogIndexMap<'a>);
 impl<'a> TryFrom<&'a str> for Dialog<'a> {
     type Error = Error<'a>;
 
-    fn try_from(s: &'a str) -> Result<Self> {
-        Ok(parser::parse(s)?)
+    fn try_from(s: &'a str) -> Result<'a, Self> {
+        parser::parse(s)
     }
 }
 
@@ -30,18 +30,18 @@ impl<'a> TryFrom<&'a str> for Dialog<'a> {
 /// FromStr doesn't support lifetimes.
 pub trait ParseExt<'a>: private::Parseable {
     /// Parse.
-    fn parse(&'a self) -> Result<Dialog<'a>>;
+    fn parse(&'a self) -> Result<'a, Dialog<'a>>;
 }
 
 impl<'a> ParseExt<'a> for str {
-    fn parse(&'a self) -> Result<Dialog<'a>> {
+    fn parse(&'a self) -> Result<'a, Dialog<'a>> {
         self.try_into()
     }
 }
 
 impl<'a> ParseExt<'a> for String {
-    fn parse(&'a self) -> Result<Dialog<'a>> {
-        (&*self as &str).try_into()
+    fn parse(&'a self) -> Result<'a, Dialog<'a>> {
+        (self as &str).try_into()
     }
 }
 
@@ -61,7 +61,7 @@ impl<'a> Extend<DialogKey<'a>> for Dialog<'a> {
 
 impl<'a> Extend<(&'a str, DialogEntry<'a>)> for Dialog<'a> {
     fn extend<I: IntoIterator<Item = (&'a str, DialogEntry<'a>)>>(&mut self, iter: I) {
-        self.0.extend(iter.into_iter())
+        self.0.extend(iter)
     }
 }
 
@@ -79,7 +79,7 @@ impl<'a> FromIterator<DialogKey<'a>> for Dialog<'a> {
 impl<'a> FromIterator<(&'a str, DialogEntry<'a>)> for Dialog<'a> {
     fn from_iter<I: IntoIterator<Item = (&'a str, DialogEntry<'a>)>>(iter: I) -> Self {
         let mut map: DialogIndexMap = Default::default();
-        map.extend(iter.into_iter());
+        map.extend(iter);
         Dialog(map)
     }
 }
@@ -178,7 +178,7 @@ pub struct DialogEntry<'a> {
 impl DialogEntry<'_> {
     /// Remove the indentation from the DialogEntry. Returns a subslice if
     /// possible, and returns a freshly allocated `String` otherwise.
-    pub fn unindent(&self) -> Cow<str> {
+    pub fn unindent(&self) -> Cow<'_, str> {
         let mut counter = 0;
         let trim = move |c| {
             if counter < self.level && (c == '\t' || c == ' ') {
@@ -10,9 +10,9 @@ use pest_derive::*;
 #[grammar = "dialog/dialog.pest"]
 struct DialogParser;
 
-pub fn parse(input: &str) -> Result<Dialog> {
+pub fn parse(input: &str) -> Result<'_, Dialog<'_>> {
     let mut map = Dialog::new();
-    let file = DialogParser::parse(Rule::file, &input)
+    let file = DialogParser::parse(Rule::file, input)
         .unwrap_or_else(|e| panic!("{}", e))
         .next()
         .expect("file always produces output upon a successful parse");
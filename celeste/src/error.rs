@@ -40,6 +40,88 @@ pub enum Error<'a> {
         #[snafu(source(false))]
         source: (&'a str, nom::error::ErrorKind),
     },
+    /// This error occurs when a `BinaryElement` file is invalid. Only returned
+    /// by `parser::parse_file` and `parser::parse_file_lossless`, which track
+    /// where in the file the error occurred.
+    #[snafu(display(
+        "Error parsing BinEl at byte {}{}: {:?}",
+        offset,
+        display_path(path),
+        kind
+    ))]
+    ParseFile {
+        /// The byte offset the error occurred at.
+        offset: usize,
+        /// The path to the element being parsed, such as
+        /// `Map/levels/level[3]/entities`. Empty if the error occurred before
+        /// the root element.
+        path: String,
+        /// The kind of error.
+        kind: nom::error::ErrorKind,
+    },
+    /// This error occurs when a `BinElType` can't be deserialized from a
+    /// `BinElValue`.
+    #[snafu(display(
+        "Expected {} but received {}{}",
+        expected,
+        received,
+        display_path(path)
+    ))]
+    Deserialize {
+        /// The path to the field, such as `solids/innerText`. Empty if the
+        /// value itself had the wrong type.
+        path: String,
+        /// The type that was expected.
+        expected: Cow<'static, str>,
+        /// The type that was received.
+        received: String,
+    },
+    /// This error occurs when an XML file passed to the library has an invalid
+    /// format.
+    #[snafu(display("Error parsing XML at byte {}: {}", position, message))]
+    ParseXml {
+        /// The byte offset the error occurred at.
+        position: usize,
+        /// A description of the error.
+        message: String,
+    },
+    /// This error occurs when a BinEl can't be written without exceeding one of
+    /// the limits of the binary format, which would otherwise be silently
+    /// truncated.
+    #[snafu(display(
+        "`{}` has {} {}, but the format allows at most {}",
+        path,
+        found,
+        limit,
+        max
+    ))]
+    LimitExceeded {
+        /// The path to the BinEl, such as `Map/levels/level[3]/entities`.
+        path: String,
+        /// What exceeded the limit, such as "attributes".
+        limit: &'static str,
+        /// The largest amount the format allows.
+        max: usize,
+        /// The amount that was found.
+        found: usize,
+    },
+    /// This error occurs when a `diff::Diff` conflicts with the `BinEl` it is
+    /// applied to.
+    #[snafu(display("Could not apply patch to `{}`: {}", path, message))]
+    Patch {
+        /// The path to the conflicting element.
+        path: String,
+        /// A description of the conflict.
+        message: String,
+    },
+    /// This error occurs when a query passed to `BinEl::select` is invalid.
+    #[snafu(display("Error parsing query at byte {}: {}", position, message))]
+    ParseQuery {
+        /// The byte offset the error occurred at.
+        position: usize,
+        /// A description of the error.
+        message: String,
+    },
     /// This error occurs when a file's data is incomplete.
     #[snafu(display("Incomplete data when parsing file"))]
     Incomplete,
@@ -63,6 +145,47 @@ impl Error<'_> {
         }
     }
 
+    /// Create an error from the expected and received type of a `BinElValue`.
+    pub fn deserialize(expected: impl Into<Cow<'static, str>>, received: String) -> Self {
+        Error::Deserialize {
+            path: String::new(),
+            expected: expected.into(),
+            received,
+        }
+    }
+
+    /// Prefix the path of a `ParseFile`, `Deserialize` or `LimitExceeded`
+    /// error with the name of the parent element or field. Other errors are
+    /// returned unchanged.
+    ///
+    /// # Examples:
+    /// ```
+    /// use celeste::Error;
+    ///
+    /// let err = Error::deserialize("i32", "text attribute".to_string())
+    ///     .in_path("width")
+    ///     .in_path("level");
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     "Expected i32 but received text attribute in `level/width`"
+    /// );
+    /// ```
+    pub fn in_path(mut self, segment: &str) -> Self {
+        match &mut self {
+            Error::ParseFile { path, .. }
+            | Error::Deserialize { path, .. }
+            | Error::LimitExceeded { path, .. } => {
+                *path = if path.is_empty() {
+                    segment.to_string()
+                } else {
+                    format!("{}/{}", segment, path)
+                };
+            }
+            _ => {}
+        }
+        self
+    }
+
     /// Shorthand for `Error::Write(...)`.
     pub fn io(kind: io::ErrorKind, text: impl Into<Box<dyn StdError + Send + Sync>>) -> Self {
         Error::Write {
@@ -71,6 +194,14 @@ impl Error<'_> {
     }
 }
 
+fn display_path(path: &str) -> String {
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!(" in `{}`", path)
+    }
+}
+
 impl From<io::Error> for Error<'_> {
     fn from(source: io::Error) -> Self {
         Error::Write {
@@ -81,7 +81,7 @@ pub struct MChat<'a> {
 }
 
 impl<'a> MChat<'a> {
-    pub fn parse(data: &'a [u8]) -> Result<(&'a [u8], Self)> {
+    pub fn parse(data: &'a [u8]) -> Result<'a, (&'a [u8], Self)> {
         Ok(map(
             tuple((le_u32, null_str, null_str, le_u8, le_u8, le_u8, le_u64)),
             From::from,
@@ -151,7 +151,7 @@ pub struct MPlayer<'a> {
 }
 
 impl<'a> MPlayer<'a> {
-    pub fn parse(data: &'a [u8]) -> Result<(&'a [u8], Self)> {
+    pub fn parse(data: &'a [u8]) -> Result<'a, (&'a [u8], Self)> {
         Ok(map(
             tuple((
                 boolean,
@@ -205,7 +205,7 @@ pub struct HHead {
 }
 
 impl HHead {
-    pub fn parse(data: &[u8]) -> Result<(&[u8], Self)> {
+    pub fn parse(data: &[u8]) -> Result<'_, (&[u8], Self)> {
         Ok(map(le_u32, From::from)(data)?)
     }
 
@@ -481,7 +481,7 @@ mod test {
             (
                 b"end" as &[u8],
                 Chunk {
-                    typ: ChunkType::Unknown("hi".into()),
+                    typ: ChunkType::Unknown("hi"),
                     data: Cow::Borrowed(&[1, 2, 3]),
                 }
             )
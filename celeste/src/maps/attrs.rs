@@ -0,0 +1,141 @@
+//! Helpers for typed elements that keep what their types don't cover:
+//! attributes and children that aren't known, and the order of attributes.
+//! Elements typed by hand use the attribute helpers, and entities and
+//! triggers are defined through `typed_object!`.
+
+use crate::binel::serialize::*;
+use crate::binel::*;
+use indexmap::IndexMap;
+use std::prelude::v1::*;
+
+pub(crate) type Attributes = IndexMap<String, BinElAttr>;
+
+/// Remove an attribute, only if it has the expected type.
+pub(crate) fn take<T: BinElType>(attrs: &mut Attributes, key: &str) -> Option<T> {
+    let val = T::from_binel(BinElValue::Attribute(attrs.get(key)?.clone())).ok()?;
+    attrs.shift_remove(key);
+    Some(val)
+}
+
+/// Set an attribute if there is a value.
+pub(crate) fn put<T: BinElType>(attrs: &mut Attributes, key: &str, val: Option<T>) {
+    if let Some(BinElValue::Attribute(attr)) = val.map(T::into_binel) {
+        attrs.insert(key.to_string(), attr);
+    }
+}
+
+/// The names of attributes, in order.
+pub(crate) fn order(attrs: &Attributes) -> Vec<String> {
+    attrs.keys().cloned().collect()
+}
+
+/// The names of children, in order.
+pub(crate) fn child_order(children: &[BinEl]) -> Vec<String> {
+    children.iter().map(|child| child.name.clone()).collect()
+}
+
+/// Typed elements that remember the order of their attributes, so that
+/// `reorder_attributes` can restore it.
+pub(crate) trait AttrOrder {
+    fn attr_order(&mut self) -> &mut Vec<String>;
+}
+
+/// Remove the first child with a name.
+pub(crate) fn take_child(children: &mut Vec<BinEl>, name: &str) -> Option<BinEl> {
+    let index = children.iter().position(|child| child.name == name)?;
+    Some(children.remove(index))
+}
+
+/// Parse a `BinEl` as `T` under another name, only if `T` keeps all of its
+/// data. The order of its attributes is kept as well.
+pub(crate) fn parse_lossless<T>(elem: &BinEl, name: &str) -> Option<T>
+where
+    T: BinElType + AttrOrder + Clone,
+{
+    let mut renamed = elem.clone();
+    renamed.name = name.to_string();
+
+    let mut typed = T::from_binel(BinElValue::Element(renamed.clone())).ok()?;
+    *typed.attr_order() = order(&renamed.attributes);
+    if typed_elem(typed.clone(), name) == renamed {
+        Some(typed)
+    } else {
+        None
+    }
+}
+
+/// Serialize `T` under another name, with its attributes in the order they
+/// were read.
+pub(crate) fn typed_elem<T: BinElType + AttrOrder>(mut typed: T, name: &str) -> BinEl {
+    let order = std::mem::take(typed.attr_order());
+    match typed.into_binel() {
+        BinElValue::Element(mut elem) => {
+            elem.name = name.to_string();
+            reorder_attributes(&mut elem.attributes, &order);
+            elem
+        }
+        _ => unreachable!("typed objects always serialize to elements"),
+    }
+}
+
+// Defines an entity or trigger. Every one has an id and a position, then the
+// fields in brackets, an optional origin, its own fields and nodes.
+macro_rules! typed_object {
+    (
+        $(#[$($meta:tt)*])*
+        $ident:ident = $name:tt [
+            $($(#[$($common_meta:tt)*])* $common:ident: $common_type:ty,)*
+        ] {
+            $($(#[$($field_meta:tt)*])* $field:ident: $type:ty,)*
+        }
+    ) => {
+        $(#[$($meta)*])*
+        #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+        #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+        #[celeste_name = $name]
+        pub struct $ident {
+            /// Unique within the `Map`.
+            pub id: i32,
+            /// The pixel (8 per tile) location in the `Level`.
+            pub x: i32,
+            /// The pixel (8 per tile) location in the `Level`.
+            pub y: i32,
+            $($(#[$($common_meta)*])* pub $common: $common_type,)*
+            pub origin_x: Option<i32>,
+            pub origin_y: Option<i32>,
+            $($(#[$($field_meta)*])* pub $field: $type,)*
+            #[celeste_child_vec]
+            pub nodes: Vec<$crate::maps::entities::Node>,
+            /// The attribute names in the order they were read. Attributes
+            /// that aren't listed are written after the rest.
+            #[celeste_skip]
+            #[cfg_attr(
+                feature = "serde",
+                serde(default, skip_serializing_if = "Vec::is_empty")
+            )]
+            pub attr_order: Vec<String>,
+        }
+
+        impl $crate::maps::attrs::AttrOrder for $ident {
+            fn attr_order(&mut self) -> &mut Vec<String> {
+                &mut self.attr_order
+            }
+        }
+    };
+}
+pub(crate) use typed_object;
+
+// Matches the typed variants of an enum of objects with one arm, and `Other`
+// with another.
+macro_rules! match_typed {
+    (
+        $value:expr, $enum:ident { $($variant:ident),* },
+        $typed:ident => $expr:expr, $other:ident => $other_expr:expr
+    ) => {
+        match $value {
+            $($enum::$variant($typed) => $expr,)*
+            $enum::Other($other) => $other_expr,
+        }
+    };
+}
+pub(crate) use match_typed;
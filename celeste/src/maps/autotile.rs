@@ -0,0 +1,590 @@
+//! Celeste's autotiler, enabled by the `xml` feature. It picks the texture of
+//! each tile in a `Solids` or `BGSolids` grid from its neighbors, using the
+//! rules in `ForegroundTiles.xml` or `BackgroundTiles.xml`.
+
+use super::tiles::TileGrid;
+use crate::binel::xml::xml_error;
+use crate::Result;
+use indexmap::IndexMap;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::prelude::v1::*;
+
+/// Which tiles a `Set` applies to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mask {
+    /// Tiles surrounded by solid tiles, that aren't `Padding`.
+    Center,
+    /// Tiles surrounded by solid tiles, but within two tiles of empty space.
+    Padding,
+    /// Tiles whose 3x3 neighborhood, row by row with the tile itself in the
+    /// middle, matches the mask. `Some(true)` is solid, `Some(false)` is
+    /// empty, and `None` matches either.
+    Neighbors([Option<bool>; 9]),
+}
+
+impl Mask {
+    /// Parse a mask, such as `x0x-111-x1x`, `center` or `padding`. Like the
+    /// game, characters other than `0`, `1` and `x` are skipped.
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "center" => return Some(Mask::Center),
+            "padding" => return Some(Mask::Padding),
+            _ => {}
+        }
+
+        let mut cells = [None; 9];
+        let mut len = 0;
+        for c in text.chars() {
+            let cell = match c {
+                '0' => Some(false),
+                '1' => Some(true),
+                'x' | 'X' => None,
+                _ => continue,
+            };
+            *cells.get_mut(len)? = cell;
+            len += 1;
+        }
+
+        if len == cells.len() {
+            Some(Mask::Neighbors(cells))
+        } else {
+            None
+        }
+    }
+}
+
+/// A rule in a `Tileset`, from a `set` element.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Set {
+    pub mask: Mask,
+    /// The tiles to choose from, as coordinates in the tileset's texture
+    /// measured in tiles.
+    pub tiles: Vec<(u32, u32)>,
+}
+
+/// A tileset definition, from a `Tileset` element.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Tileset {
+    /// The character used for the tileset in `Solids` and `BGSolids`.
+    pub id: char,
+    /// The texture, relative to `tilesets/` in the Gameplay atlas.
+    pub path: String,
+    /// A tileset whose `sets` are used after this one's own.
+    pub copy: Option<char>,
+    /// Tilesets that are treated as empty space next to this one. `*` ignores
+    /// every other tileset.
+    pub ignores: Vec<char>,
+    pub sets: Vec<Set>,
+}
+
+impl Tileset {
+    /// Whether a neighboring tile is treated as empty space.
+    pub fn ignores(&self, tile: char) -> bool {
+        tile != self.id && (self.ignores.contains(&tile) || self.ignores.contains(&'*'))
+    }
+}
+
+type Tiles = Vec<(u32, u32)>;
+
+/// The `Set`s of a `Tileset` after `copy` is applied.
+#[derive(Clone, Default, Debug)]
+struct Rules {
+    center: Tiles,
+    padding: Tiles,
+    neighbors: Vec<([Option<bool>; 9], Tiles)>,
+}
+
+impl Rules {
+    fn add(&mut self, sets: &[Set]) {
+        for set in sets {
+            match set.mask {
+                Mask::Center => self.center.extend(&set.tiles),
+                Mask::Padding => self.padding.extend(&set.tiles),
+                Mask::Neighbors(mask) => self.neighbors.push((mask, set.tiles.clone())),
+            }
+        }
+
+        // The most specific masks are tried first.
+        self.neighbors
+            .sort_by_key(|(mask, _)| mask.iter().filter(|cell| cell.is_none()).count());
+    }
+}
+
+/// The result of autotiling a single tile.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Cell {
+    Empty,
+    /// A tile from a tileset's texture, measured in tiles.
+    Tile {
+        tileset: char,
+        x: u32,
+        y: u32,
+    },
+    /// No rule in the tileset matches the tile's neighbors, so the game
+    /// leaves it blank.
+    NoRule(char),
+    /// The tile's tileset isn't defined.
+    UnknownTileset(char),
+}
+
+/// A grid of `Cell`s, the same size as the `TileGrid` it was generated from.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Autotiled {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Autotiled {
+    /// The width in tiles.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The height in tiles.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Get a cell, if it is inside the grid.
+    pub fn get(&self, x: usize, y: usize) -> Option<Cell> {
+        if x < self.width && y < self.height {
+            Some(self.cells[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    /// The rows of the grid, from top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = &[Cell]> + '_ {
+        self.cells.chunks(self.width.max(1)).take(self.height)
+    }
+
+    /// The position and tileset of every tile that couldn't be autotiled,
+    /// either because no rule matched or because its tileset isn't defined.
+    pub fn unmatched(&self) -> impl Iterator<Item = (usize, usize, char)> + '_ {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, cell)| match cell {
+                Cell::NoRule(tile) | Cell::UnknownTileset(tile) => {
+                    Some((i % self.width, i / self.width, *tile))
+                }
+                _ => None,
+            })
+    }
+}
+
+/// Tileset definitions, parsed from `ForegroundTiles.xml` or
+/// `BackgroundTiles.xml`.
+///
+/// # Examples:
+/// ```
+/// use celeste::maps::autotile::*;
+/// use celeste::maps::tiles::TileGrid;
+///
+/// let autotiler = Autotiler::parse(
+///     r#"<Data>
+///         <Tileset id="1" path="dirt">
+///             <set mask="x0x-111-x1x" tiles="1,0"/>
+///             <set mask="center" tiles="1,1"/>
+///             <set mask="padding" tiles="1,2"/>
+///         </Tileset>
+///     </Data>"#,
+/// )
+/// .unwrap();
+///
+/// let grid = TileGrid::<char>::parse("000\n111\n111", 3, 3).unwrap();
+/// let tiles = autotiler.autotile(&grid);
+/// assert_eq!(tiles.get(1, 1), Some(Cell::Tile { tileset: '1', x: 1, y: 0 }));
+/// assert_eq!(tiles.get(1, 2), Some(Cell::Tile { tileset: '1', x: 1, y: 2 }));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Autotiler {
+    tilesets: IndexMap<char, Tileset>,
+    rules: IndexMap<char, Rules>,
+}
+
+impl Autotiler {
+    /// Parse tileset definitions. Like the game, a `Tileset` can only `copy`
+    /// one defined before it.
+    pub fn parse(xml: &str) -> Result<'static, Self> {
+        let mut reader = Reader::from_str(xml);
+        let mut buf = Vec::new();
+        let mut autotiler = Autotiler {
+            tilesets: IndexMap::new(),
+            rules: IndexMap::new(),
+        };
+        let mut current = None;
+
+        loop {
+            buf.clear();
+            let (start, empty) = match reader.read_event(&mut buf) {
+                Ok(Event::Start(start)) => (start, false),
+                Ok(Event::Empty(start)) => (start, true),
+                Ok(Event::End(end)) => {
+                    if end.name() == b"Tileset" {
+                        if let Some(tileset) = current.take() {
+                            autotiler.add(&reader, tileset)?;
+                        }
+                    }
+                    continue;
+                }
+                Ok(Event::Eof) => break,
+                Ok(_) => continue,
+                Err(err) => return Err(xml_error(&reader, err)),
+            };
+
+            let attrs = read_attrs(&reader, &start)?;
+            match start.name() {
+                b"Tileset" if current.is_some() => {
+                    return Err(xml_error(&reader, "`Tileset` inside of a `Tileset`"));
+                }
+                b"Tileset" => {
+                    let tileset = read_tileset(&reader, &attrs)?;
+                    if empty {
+                        autotiler.add(&reader, tileset)?;
+                    } else {
+                        current = Some(tileset);
+                    }
+                }
+                b"set" => {
+                    let set = read_set(&reader, &attrs)?;
+                    match &mut current {
+                        Some(tileset) => tileset.sets.push(set),
+                        None => return Err(xml_error(&reader, "`set` outside of a `Tileset`")),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if current.is_some() {
+            return Err(xml_error(&reader, "`Tileset` was not completed"));
+        }
+        Ok(autotiler)
+    }
+
+    fn add(&mut self, reader: &Reader<&[u8]>, tileset: Tileset) -> Result<'static, ()> {
+        if self.tilesets.contains_key(&tileset.id) {
+            let message = format!("Tileset `{}` is defined more than once", tileset.id);
+            return Err(xml_error(reader, message));
+        }
+
+        let mut rules = Rules::default();
+        rules.add(&tileset.sets);
+        if let Some(copy) = tileset.copy {
+            match self.tilesets.get(&copy) {
+                Some(copied) => rules.add(&copied.sets),
+                None => {
+                    let message = format!("Tileset `{}` copies unknown `{}`", tileset.id, copy);
+                    return Err(xml_error(reader, message));
+                }
+            }
+        }
+
+        self.rules.insert(tileset.id, rules);
+        self.tilesets.insert(tileset.id, tileset);
+        Ok(())
+    }
+
+    /// Get a tileset by its id.
+    pub fn tileset(&self, id: char) -> Option<&Tileset> {
+        self.tilesets.get(&id)
+    }
+
+    /// Every tileset, in the order they were defined.
+    pub fn tilesets(&self) -> impl Iterator<Item = &Tileset> + '_ {
+        self.tilesets.values()
+    }
+
+    /// Pick a tile for every cell of a grid, the way the game does for a room
+    /// on its own: tiles past the edge of the grid are treated as copies of
+    /// the nearest edge tile.
+    ///
+    /// When a rule has more than one tile, the game picks randomly. This
+    /// picks based on the tile's position instead, so the result is
+    /// deterministic, but may not match the game's choice.
+    pub fn autotile(&self, grid: &TileGrid<char>) -> Autotiled {
+        let (width, height) = (grid.width(), grid.height());
+        let mut cells = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                cells.push(self.cell(grid, x, y));
+            }
+        }
+
+        Autotiled {
+            width,
+            height,
+            cells,
+        }
+    }
+
+    fn cell(&self, grid: &TileGrid<char>, x: usize, y: usize) -> Cell {
+        let tile = grid.get(x, y).unwrap_or('0');
+        if is_empty(tile) {
+            return Cell::Empty;
+        }
+        let (tileset, rules) = match (self.tilesets.get(&tile), self.rules.get(&tile)) {
+            (Some(tileset), Some(rules)) => (tileset, rules),
+            _ => return Cell::UnknownTileset(tile),
+        };
+
+        let (width, height) = (grid.width(), grid.height());
+        let solid = |dx: isize, dy: isize| {
+            let clamp = |pos: usize, delta: isize, len: usize| {
+                (pos as isize + delta).clamp(0, len as isize - 1) as usize
+            };
+            match grid.get(clamp(x, dx, width), clamp(y, dy, height)) {
+                Some(neighbor) => !is_empty(neighbor) && !tileset.ignores(neighbor),
+                None => false,
+            }
+        };
+
+        let mut neighbors = [false; 9];
+        for (i, neighbor) in neighbors.iter_mut().enumerate() {
+            *neighbor = solid(i as isize % 3 - 1, i as isize / 3 - 1);
+        }
+
+        let tiles = if neighbors.iter().all(|solid| *solid) {
+            // Padding only looks at tiles inside of the grid.
+            let padded = (x > 1 && !solid(-2, 0))
+                || (x + 2 < width && !solid(2, 0))
+                || (y > 1 && !solid(0, -2))
+                || (y + 2 < height && !solid(0, 2));
+            if padded {
+                &rules.padding
+            } else {
+                &rules.center
+            }
+        } else {
+            let matched = rules.neighbors.iter().find(|(mask, _)| {
+                mask.iter()
+                    .zip(&neighbors)
+                    .all(|(cell, solid)| cell.is_none_or(|cell| cell == *solid))
+            });
+            match matched {
+                Some((_, tiles)) => tiles,
+                None => return Cell::NoRule(tile),
+            }
+        };
+
+        if tiles.is_empty() {
+            return Cell::NoRule(tile);
+        }
+        let variant = (x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663)) % tiles.len();
+        let (tile_x, tile_y) = tiles[variant];
+        Cell::Tile {
+            tileset: tile,
+            x: tile_x,
+            y: tile_y,
+        }
+    }
+}
+
+fn is_empty(tile: char) -> bool {
+    tile == '0' || tile == '\0'
+}
+
+fn read_attrs(
+    reader: &Reader<&[u8]>,
+    start: &BytesStart,
+) -> Result<'static, IndexMap<String, String>> {
+    let mut attrs = IndexMap::new();
+    for attr in start.attributes() {
+        let attr = attr.map_err(|err| xml_error(reader, err))?;
+        let key = reader
+            .decode(attr.key)
+            .map_err(|err| xml_error(reader, err))?
+            .to_string();
+        let value = attr
+            .unescape_and_decode_value(reader)
+            .map_err(|err| xml_error(reader, err))?;
+        attrs.insert(key, value);
+    }
+    Ok(attrs)
+}
+
+fn read_char(
+    reader: &Reader<&[u8]>,
+    attrs: &IndexMap<String, String>,
+    key: &str,
+) -> Result<'static, Option<char>> {
+    let value = match attrs.get(key) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(Some(c)),
+        _ => {
+            let message = format!("Expected a single character for `{}`, got `{}`", key, value);
+            Err(xml_error(reader, message))
+        }
+    }
+}
+
+fn read_tileset(
+    reader: &Reader<&[u8]>,
+    attrs: &IndexMap<String, String>,
+) -> Result<'static, Tileset> {
+    let id = match read_char(reader, attrs, "id")? {
+        Some(id) => id,
+        None => return Err(xml_error(reader, "`Tileset` is missing an `id`")),
+    };
+
+    let ignores = attrs
+        .get("ignores")
+        .map(|ignores| {
+            ignores
+                .split(',')
+                .filter_map(|ignore| ignore.chars().next())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(Tileset {
+        id,
+        path: attrs.get("path").cloned().unwrap_or_default(),
+        copy: read_char(reader, attrs, "copy")?,
+        ignores,
+        sets: Vec::new(),
+    })
+}
+
+fn read_set(reader: &Reader<&[u8]>, attrs: &IndexMap<String, String>) -> Result<'static, Set> {
+    let mask = attrs.get("mask").map(String::as_str).unwrap_or_default();
+    let mask = match Mask::parse(mask) {
+        Some(mask) => mask,
+        None => return Err(xml_error(reader, format!("Invalid mask `{}`", mask))),
+    };
+
+    let tiles = attrs.get("tiles").map(String::as_str).unwrap_or_default();
+    let tiles = tiles
+        .split(';')
+        .map(|tile| {
+            let mut coords = tile.split(',').map(|coord| coord.trim().parse().ok());
+            match (coords.next(), coords.next(), coords.next()) {
+                (Some(Some(x)), Some(Some(y)), None) => Some((x, y)),
+                _ => None,
+            }
+        })
+        .collect::<Option<_>>();
+    match tiles {
+        Some(tiles) => Ok(Set { mask, tiles }),
+        None => {
+            let tiles = attrs.get("tiles").map(String::as_str).unwrap_or_default();
+            Err(xml_error(reader, format!("Invalid tiles `{}`", tiles)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TILES: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+        <Data>
+            <!-- dirt -->
+            <Tileset id="1" path="dirt" sound="3">
+                <set mask="x0x-111-x1x" tiles="0,0; 1,0"/>
+                <set mask="x0x-011-x1x" tiles="0,1"/>
+                <set mask="x0x-110-x1x" tiles="2,1"/>
+                <set mask="center" tiles="1,1"/>
+                <set mask="padding" tiles="1,2"/>
+            </Tileset>
+            <Tileset id="3" copy="1" path="snow" ignores="1"/>
+        </Data>"#;
+
+    #[test]
+    fn parse_tilesets() {
+        let autotiler = Autotiler::parse(TILES).unwrap();
+        let ids: Vec<char> = autotiler.tilesets().map(|tileset| tileset.id).collect();
+        assert_eq!(ids, vec!['1', '3']);
+
+        let dirt = autotiler.tileset('1').unwrap();
+        assert_eq!(dirt.path, "dirt");
+        assert_eq!(dirt.sets.len(), 5);
+        assert_eq!(dirt.sets[0].tiles, vec![(0, 0), (1, 0)]);
+        assert_eq!(
+            dirt.sets[1].mask,
+            Mask::Neighbors([
+                None,
+                Some(false),
+                None,
+                Some(false),
+                Some(true),
+                Some(true),
+                None,
+                Some(true),
+                None
+            ])
+        );
+
+        let snow = autotiler.tileset('3').unwrap();
+        assert_eq!((snow.copy, snow.sets.len()), (Some('1'), 0));
+        assert!(snow.ignores('1'));
+        assert!(!snow.ignores('3'));
+        assert!(!dirt.ignores('3'));
+    }
+
+    #[test]
+    fn autotile_grid() {
+        let autotiler = Autotiler::parse(TILES).unwrap();
+        let grid = TileGrid::<char>::parse("00000\n11339\n1133", 5, 3).unwrap();
+        let tiles = autotiler.autotile(&grid);
+        assert_eq!(tiles, autotiler.autotile(&grid));
+
+        assert_eq!(tiles.get(0, 0), Some(Cell::Empty));
+        match tiles.get(1, 1) {
+            Some(Cell::Tile {
+                tileset: '1',
+                x,
+                y: 0,
+            }) => assert!(x < 2),
+            other => panic!("Expected a top edge tile, got {:?}", other),
+        }
+        assert_eq!(
+            tiles.get(1, 2),
+            Some(Cell::Tile {
+                tileset: '1',
+                x: 1,
+                y: 2
+            })
+        );
+        // Snow uses dirt's rules, but treats dirt as empty space.
+        assert_eq!(
+            tiles.get(2, 1),
+            Some(Cell::Tile {
+                tileset: '3',
+                x: 0,
+                y: 1
+            })
+        );
+
+        let unmatched: Vec<_> = tiles.unmatched().collect();
+        assert_eq!(unmatched, vec![(4, 1, '9'), (2, 2, '3'), (3, 2, '3')]);
+        assert_eq!(tiles.get(4, 1), Some(Cell::UnknownTileset('9')));
+    }
+
+    #[test]
+    fn invalid_tilesets() {
+        let parse = |xml: &str| Autotiler::parse(xml).map(|_| ());
+
+        assert!(
+            parse(r#"<Data><Tileset id="1"><set mask="11" tiles="0,0"/></Tileset></Data>"#)
+                .is_err()
+        );
+        assert!(
+            parse(r#"<Data><Tileset id="1"><set mask="center" tiles="0"/></Tileset></Data>"#)
+                .is_err()
+        );
+        assert!(parse(r#"<Data><Tileset id="1" copy="2"/><Tileset id="2"/></Data>"#).is_err());
+        assert!(parse(r#"<Data><Tileset id="1"/><Tileset id="1"/></Data>"#).is_err());
+        assert!(parse(r#"<Data><Tileset id="12"/></Data>"#).is_err());
+        assert!(parse(r#"<Data><set mask="center" tiles="0,0"/></Data>"#).is_err());
+    }
+}
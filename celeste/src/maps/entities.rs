@@ -0,0 +1,436 @@
+//! Typed definitions for the vanilla entities. Entities that aren't known, or
+//! that can't be represented by their typed definition without losing data,
+//! are kept as a raw `BinEl` in `Entity::Other`.
+
+use super::attrs::{match_typed, parse_lossless, typed_elem, typed_object};
+use crate::binel::serialize::*;
+use crate::binel::*;
+use crate::Result;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::prelude::v1::*;
+
+/// An extra position of an `Entity` or `Trigger`, such as where a `ZipMover`
+/// moves to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[celeste_name = "node"]
+pub struct Node {
+    /// The pixel (8 per tile) location in the `Level`.
+    pub x: i32,
+    /// The pixel (8 per tile) location in the `Level`.
+    pub y: i32,
+}
+
+// Entities have no common fields beyond the id and position.
+macro_rules! entity {
+    ($(#[$($meta:tt)*])* $ident:ident = $name:tt { $($fields:tt)* }) => {
+        typed_object! { $(#[$($meta)*])* $ident = $name [] { $($fields)* } }
+    };
+}
+
+entity! {
+    /// Where the player spawns. Every `Level` needs at least one.
+    Player = "player" {}
+}
+
+entity! {
+    /// A strawberry, collectible or not.
+    Strawberry = "strawberry" {
+        winged: bool,
+        /// The checkpoint the strawberry belongs to, for the chapter select.
+        #[celeste_name = "checkpointID"]
+        checkpoint_id: i32,
+        /// The position of the strawberry within its checkpoint.
+        order: i32,
+        /// Everest extension.
+        moon: Option<bool>,
+    }
+}
+
+/// The direction `Spikes` point in, stored as part of the element name.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[allow(missing_docs)]
+pub enum Direction {
+    #[default]
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+entity! {
+    /// A row of spikes, stored as `spikesUp`, `spikesDown`, `spikesLeft` or
+    /// `spikesRight`.
+    Spikes = "spikes" {
+        /// Set for spikes pointing up or down.
+        width: Option<i32>,
+        /// Set for spikes pointing left or right.
+        height: Option<i32>,
+        /// The texture, such as `default` or `cliffside`.
+        #[celeste_name = "type"]
+        kind: Option<String>,
+        #[celeste_skip]
+        direction: Direction,
+    }
+}
+
+entity! {
+    /// A platform that can be jumped through from below.
+    JumpThru = "jumpThru" {
+        width: i32,
+        texture: String,
+        /// The sound made when landing on it. -1 uses the texture's default.
+        surface_index: Option<i32>,
+    }
+}
+
+entity! {
+    /// A dash crystal.
+    Refill = "refill" {
+        two_dash: bool,
+        one_use: bool,
+    }
+}
+
+/// Where a `Spring` is attached, stored as part of the element name.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Orientation {
+    /// Attached to the floor, stored as `spring`.
+    #[default]
+    Floor,
+    /// Attached to a wall on the left, stored as `wallSpringLeft`.
+    WallLeft,
+    /// Attached to a wall on the right, stored as `wallSpringRight`.
+    WallRight,
+}
+
+entity! {
+    /// A spring, on either the floor or a wall.
+    Spring = "spring" {
+        /// Everest extension.
+        player_can_use: Option<bool>,
+        #[celeste_skip]
+        orientation: Orientation,
+    }
+}
+
+entity! {
+    /// A block that moves to its node when touched, then returns.
+    ZipMover = "zipMover" {
+        width: i32,
+        height: i32,
+        /// Either `Normal` or `Moon`.
+        theme: Option<String>,
+    }
+}
+
+entity! {
+    /// A space jam block, which can be dashed through.
+    DreamBlock = "dreamBlock" {
+        width: i32,
+        height: i32,
+        fast_moving: bool,
+        one_use: Option<bool>,
+        below: Option<bool>,
+    }
+}
+
+entity! {
+    /// A block that falls when stood on or climbed.
+    FallingBlock = "fallingBlock" {
+        width: i32,
+        height: i32,
+        /// The tileset, as a single character.
+        tiletype: String,
+        climb_fall: bool,
+        behind: bool,
+    }
+}
+
+entity! {
+    /// A row of blocks that crumble when stood on, then reform.
+    CrumbleBlock = "crumbleBlock" {
+        width: i32,
+        texture: String,
+    }
+}
+
+entity! {
+    /// A bubble that launches the player.
+    Booster = "booster" {
+        red: bool,
+    }
+}
+
+/// An entity in a `Level`.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[allow(missing_docs)]
+pub enum Entity {
+    Player(Player),
+    Strawberry(Strawberry),
+    Spikes(Spikes),
+    JumpThru(JumpThru),
+    Refill(Refill),
+    Spring(Spring),
+    ZipMover(ZipMover),
+    DreamBlock(DreamBlock),
+    FallingBlock(FallingBlock),
+    CrumbleBlock(CrumbleBlock),
+    Booster(Booster),
+    /// An unknown entity, or one that couldn't be fully represented by its
+    /// typed definition.
+    Other(BinEl),
+}
+
+macro_rules! each_typed {
+    ($entity:expr, $($arms:tt)*) => {
+        match_typed!(
+            $entity,
+            Entity {
+                Player,
+                Strawberry,
+                Spikes,
+                JumpThru,
+                Refill,
+                Spring,
+                ZipMover,
+                DreamBlock,
+                FallingBlock,
+                CrumbleBlock,
+                Booster
+            },
+            $($arms)*
+        )
+    };
+}
+
+impl Entity {
+    /// Parse an entity from a `BinEl`, falling back to `Entity::Other` if it is
+    /// unknown or any of its data would be lost.
+    ///
+    /// # Examples:
+    /// ```
+    /// use celeste::binel::*;
+    /// use celeste::maps::entities::*;
+    ///
+    /// let spikes = BinEl::build("spikesLeft")
+    ///     .attr("id", 3)
+    ///     .attr("x", 0)
+    ///     .attr("y", 16)
+    ///     .attr("height", 24)
+    ///     .finish();
+    ///
+    /// match Entity::from_elem(&spikes) {
+    ///     Entity::Spikes(spikes) => {
+    ///         assert_eq!(spikes.direction, Direction::Left);
+    ///         assert_eq!(spikes.height, Some(24));
+    ///     }
+    ///     other => panic!("Expected spikes, got {:?}", other),
+    /// }
+    ///
+    /// let modded = BinEl::build("FrostHelper/IceSpinner").attr("id", 4).finish();
+    /// assert_eq!(Entity::from_elem(&modded), Entity::Other(modded));
+    /// ```
+    pub fn from_elem(elem: &BinEl) -> Self {
+        fn spikes(elem: &BinEl, direction: Direction) -> Option<Entity> {
+            parse_lossless(elem, "spikes").map(|spikes| {
+                Entity::Spikes(Spikes {
+                    direction,
+                    ..spikes
+                })
+            })
+        }
+
+        fn spring(elem: &BinEl, orientation: Orientation) -> Option<Entity> {
+            parse_lossless(elem, "spring").map(|spring| {
+                Entity::Spring(Spring {
+                    orientation,
+                    ..spring
+                })
+            })
+        }
+
+        let name = elem.name.as_str();
+        let typed = match name {
+            "player" => parse_lossless(elem, name).map(Entity::Player),
+            "strawberry" => parse_lossless(elem, name).map(Entity::Strawberry),
+            "spikesUp" => spikes(elem, Direction::Up),
+            "spikesDown" => spikes(elem, Direction::Down),
+            "spikesLeft" => spikes(elem, Direction::Left),
+            "spikesRight" => spikes(elem, Direction::Right),
+            "jumpThru" => parse_lossless(elem, name).map(Entity::JumpThru),
+            "refill" => parse_lossless(elem, name).map(Entity::Refill),
+            "spring" => spring(elem, Orientation::Floor),
+            "wallSpringLeft" => spring(elem, Orientation::WallLeft),
+            "wallSpringRight" => spring(elem, Orientation::WallRight),
+            "zipMover" => parse_lossless(elem, name).map(Entity::ZipMover),
+            "dreamBlock" => parse_lossless(elem, name).map(Entity::DreamBlock),
+            "fallingBlock" => parse_lossless(elem, name).map(Entity::FallingBlock),
+            "crumbleBlock" => parse_lossless(elem, name).map(Entity::CrumbleBlock),
+            "booster" => parse_lossless(elem, name).map(Entity::Booster),
+            _ => None,
+        };
+
+        typed.unwrap_or_else(|| Entity::Other(elem.clone()))
+    }
+
+    /// Convert the entity back into a `BinEl`.
+    pub fn into_elem(self) -> BinEl {
+        let name = self.name().to_string();
+        each_typed!(self, typed => typed_elem(typed, &name), elem => elem)
+    }
+
+    /// The element name of the entity, such as `spikesUp`.
+    pub fn name(&self) -> &str {
+        match self {
+            Entity::Player(_) => "player",
+            Entity::Strawberry(_) => "strawberry",
+            Entity::Spikes(spikes) => match spikes.direction {
+                Direction::Up => "spikesUp",
+                Direction::Down => "spikesDown",
+                Direction::Left => "spikesLeft",
+                Direction::Right => "spikesRight",
+            },
+            Entity::JumpThru(_) => "jumpThru",
+            Entity::Refill(_) => "refill",
+            Entity::Spring(spring) => match spring.orientation {
+                Orientation::Floor => "spring",
+                Orientation::WallLeft => "wallSpringLeft",
+                Orientation::WallRight => "wallSpringRight",
+            },
+            Entity::ZipMover(_) => "zipMover",
+            Entity::DreamBlock(_) => "dreamBlock",
+            Entity::FallingBlock(_) => "fallingBlock",
+            Entity::CrumbleBlock(_) => "crumbleBlock",
+            Entity::Booster(_) => "booster",
+            Entity::Other(elem) => &elem.name,
+        }
+    }
+
+    /// The entity's id, if it has one.
+    pub fn id(&self) -> Option<i32> {
+        each_typed!(self, typed => Some(typed.id), elem => elem.attr_i32("id").ok())
+    }
+
+    /// The entity's pixel location in the `Level`, if it has one.
+    pub fn position(&self) -> Option<(i32, i32)> {
+        each_typed!(
+            self,
+            typed => Some((typed.x, typed.y)),
+            elem => Some((elem.attr_i32("x").ok()?, elem.attr_i32("y").ok()?))
+        )
+    }
+
+    /// The entity's width and height in pixels, for those it has.
+    pub fn size(&self) -> (Option<i32>, Option<i32>) {
+        match self {
+            Entity::Spikes(spikes) => (spikes.width, spikes.height),
+            Entity::JumpThru(jump_thru) => (Some(jump_thru.width), None),
+            Entity::ZipMover(zip) => (Some(zip.width), Some(zip.height)),
+            Entity::DreamBlock(block) => (Some(block.width), Some(block.height)),
+            Entity::FallingBlock(block) => (Some(block.width), Some(block.height)),
+            Entity::CrumbleBlock(block) => (Some(block.width), None),
+            Entity::Player(_)
+            | Entity::Strawberry(_)
+            | Entity::Refill(_)
+            | Entity::Spring(_)
+            | Entity::Booster(_) => (None, None),
+            Entity::Other(elem) => (elem.attr_i32("width").ok(), elem.attr_i32("height").ok()),
+        }
+    }
+
+    /// The entity's nodes. Nodes of an `Entity::Other` that can't be parsed
+    /// are skipped.
+    pub fn nodes(&self) -> Vec<Node> {
+        each_typed!(
+            self,
+            typed => typed.nodes.clone(),
+            elem => elem
+                .get("node")
+                .filter_map(|node| Node::from_binel(BinElValue::Element(node.clone())).ok())
+                .collect()
+        )
+    }
+}
+
+impl BinElType for Entity {
+    fn into_binel(self) -> BinElValue {
+        BinElValue::Element(self.into_elem())
+    }
+
+    fn from_binel(binel: BinElValue) -> Result<'static, Self> {
+        BinEl::from_binel(binel).map(|elem| Entity::from_elem(&elem))
+    }
+
+    fn maybe_attr() -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // not in field order, to check that the order is kept
+    fn zip_mover() -> BinEl {
+        BinEl::build("zipMover")
+            .attr("theme", "Moon")
+            .attr("width", 16)
+            .attr("height", 24)
+            .attr("id", 1)
+            .attr("x", 8)
+            .attr("y", 16)
+            .child(BinEl::build("node").attr("x", 40).attr("y", 16))
+            .finish()
+    }
+
+    #[test]
+    fn typed_roundtrip() {
+        let entity = Entity::from_elem(&zip_mover());
+        match &entity {
+            Entity::ZipMover(zip) => {
+                assert_eq!(zip.theme.as_deref(), Some("Moon"));
+                assert_eq!(zip.nodes, vec![Node { x: 40, y: 16 }]);
+            }
+            other => panic!("Expected zip mover, got {:?}", other),
+        }
+        assert_eq!(entity.id(), Some(1));
+        assert_eq!(entity.size(), (Some(16), Some(24)));
+        // equality ignores the order of attributes
+        let elem = entity.clone().into_elem();
+        assert_eq!(elem, zip_mover());
+        assert!(elem.attributes.keys().eq(zip_mover().attributes.keys()));
+
+        let spring = BinEl::build("wallSpringRight")
+            .attr("id", 2)
+            .attr("x", 0)
+            .attr("y", 0)
+            .finish();
+        let entity = Entity::from_elem(&spring);
+        assert_eq!(entity.name(), "wallSpringRight");
+        assert_eq!(entity.into_elem(), spring);
+    }
+
+    #[test]
+    fn lossy_stays_raw() {
+        let mut extra = zip_mover();
+        extra
+            .attributes
+            .insert("modded".to_string(), BinElAttr::Bool(true));
+        assert_eq!(Entity::from_elem(&extra), Entity::Other(extra.clone()));
+
+        let mut wrong_type = zip_mover();
+        wrong_type
+            .attributes
+            .insert("theme".to_string(), BinElAttr::Int(1));
+        let entity = Entity::from_elem(&wrong_type);
+        assert_eq!(entity, Entity::Other(wrong_type));
+        assert_eq!(entity.position(), Some((8, 16)));
+        assert_eq!(entity.size(), (Some(16), Some(24)));
+        assert_eq!(entity.nodes(), vec![Node { x: 40, y: 16 }]);
+    }
+}
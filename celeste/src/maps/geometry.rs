@@ -0,0 +1,110 @@
+//! Rectangles in world space, used for room geometry.
+
+use super::entities::Direction;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// An axis-aligned rectangle, measured in pixels. The right and bottom edges
+/// are exclusive, and saturate rather than overflow.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    /// Create a rectangle from its top left corner and size.
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// The x coordinate just past the right edge.
+    pub fn right(&self) -> i32 {
+        self.x.saturating_add(self.width)
+    }
+
+    /// The y coordinate just past the bottom edge.
+    pub fn bottom(&self) -> i32 {
+        self.y.saturating_add(self.height)
+    }
+
+    /// Whether the rectangle covers a point.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    /// Whether the rectangles share any area. Rectangles that only touch
+    /// don't overlap.
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        self.x < other.right()
+            && other.x < self.right()
+            && self.y < other.bottom()
+            && other.y < self.bottom()
+    }
+
+    /// The side of this rectangle that `other` is against, if they share part
+    /// of an edge. Rectangles that only touch at a corner aren't adjacent.
+    ///
+    /// # Examples:
+    /// ```
+    /// use celeste::maps::entities::Direction;
+    /// use celeste::maps::geometry::Rect;
+    ///
+    /// let room = Rect::new(0, 0, 320, 184);
+    /// assert_eq!(room.adjacent_side(&Rect::new(320, 100, 320, 184)), Some(Direction::Right));
+    /// assert_eq!(room.adjacent_side(&Rect::new(-40, -184, 40, 184)), None);
+    /// ```
+    pub fn adjacent_side(&self, other: &Rect) -> Option<Direction> {
+        let spans_x = self.x < other.right() && other.x < self.right();
+        let spans_y = self.y < other.bottom() && other.y < self.bottom();
+
+        if spans_y && other.right() == self.x {
+            Some(Direction::Left)
+        } else if spans_y && other.x == self.right() {
+            Some(Direction::Right)
+        } else if spans_x && other.bottom() == self.y {
+            Some(Direction::Up)
+        } else if spans_x && other.y == self.bottom() {
+            Some(Direction::Down)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn overlap_and_adjacency() {
+        let room = Rect::new(0, 0, 40, 24);
+
+        assert!(room.overlaps(&Rect::new(39, 23, 8, 8)));
+        assert!(!room.overlaps(&Rect::new(40, 0, 8, 8)));
+        assert!(!room.overlaps(&Rect::new(0, 0, 0, 0)));
+
+        assert_eq!(
+            room.adjacent_side(&Rect::new(0, -8, 8, 8)),
+            Some(Direction::Up)
+        );
+        assert_eq!(
+            room.adjacent_side(&Rect::new(32, 24, 16, 8)),
+            Some(Direction::Down)
+        );
+        assert_eq!(
+            room.adjacent_side(&Rect::new(-8, 0, 8, 8)),
+            Some(Direction::Left)
+        );
+        assert_eq!(room.adjacent_side(&Rect::new(40, 24, 8, 8)), None);
+        assert_eq!(room.adjacent_side(&Rect::new(8, 8, 8, 8)), None);
+    }
+}
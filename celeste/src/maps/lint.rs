@@ -0,0 +1,527 @@
+//! Checks for common mistakes in a parsed `Map`. Each check is a `Lint`, and
+//! a `Linter` runs a set of them, collecting `Diagnostic`s.
+//!
+//! # Examples:
+//! ```
+//! use celeste::maps::lint::*;
+//! use celeste::maps::*;
+//!
+//! let mut map = Map::default();
+//! map.levels.levels.push(Level {
+//!     name: "a-00".to_string(),
+//!     width: 320,
+//!     height: 184,
+//!     ..Level::default()
+//! });
+//!
+//! let diagnostics = Linter::default().run(&map);
+//! assert_eq!(diagnostics.len(), 1);
+//! assert_eq!(diagnostics[0].lint, "no_player_spawn");
+//! assert_eq!(diagnostics[0].level.as_deref(), Some("a-00"));
+//!
+//! let diagnostics = Linter::default().without("no_player_spawn").run(&map);
+//! assert!(diagnostics.is_empty());
+//! ```
+
+use super::geometry::Rect;
+use super::{Level, Map};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::prelude::v1::*;
+
+/// How serious a `Diagnostic` is.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Severity {
+    /// Likely a mistake, but the game can load the `Map`.
+    Warning,
+    /// The `Map` is broken, or data will be lost.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found by a `Lint`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Diagnostic {
+    /// The name of the `Lint` that found the problem.
+    pub lint: &'static str,
+    pub severity: Severity,
+    /// The name of the `Level` the problem is in, if it is in one.
+    pub level: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[{}]", self.severity, self.lint)?;
+        if let Some(level) = &self.level {
+            write!(f, " in `{}`", level)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Collects the `Diagnostic`s found by a single `Lint`.
+#[derive(Debug)]
+pub struct Report {
+    lint: &'static str,
+    severity: Severity,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    fn push(&mut self, level: Option<String>, message: String) {
+        self.diagnostics.push(Diagnostic {
+            lint: self.lint,
+            severity: self.severity,
+            level,
+            message,
+        });
+    }
+
+    /// Report a problem with the `Map` as a whole.
+    pub fn map(&mut self, message: impl Into<String>) {
+        self.push(None, message.into());
+    }
+
+    /// Report a problem in a `Level`.
+    pub fn level(&mut self, level: &Level, message: impl Into<String>) {
+        self.push(Some(level.name.clone()), message.into());
+    }
+}
+
+/// A check that can be run over a `Map`.
+pub trait Lint {
+    /// A unique name for the check, in `snake_case`.
+    fn name(&self) -> &'static str;
+
+    /// The severity of every `Diagnostic` the check reports.
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    /// Check a `Map`, reporting any problems.
+    fn check(&self, map: &Map, report: &mut Report);
+}
+
+/// A set of `Lint`s to run. `Linter::default()` includes every built-in
+/// check, while `Linter::new()` starts empty.
+pub struct Linter {
+    lints: Vec<Box<dyn Lint>>,
+}
+
+impl Linter {
+    /// Create a `Linter` with no checks.
+    pub fn new() -> Self {
+        Linter { lints: Vec::new() }
+    }
+
+    /// Add a check.
+    pub fn with(mut self, lint: impl Lint + 'static) -> Self {
+        self.lints.push(Box::new(lint));
+        self
+    }
+
+    /// Remove every check with a name.
+    pub fn without(mut self, name: &str) -> Self {
+        self.lints.retain(|lint| lint.name() != name);
+        self
+    }
+
+    /// The names of the checks that will be run.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.lints.iter().map(|lint| lint.name())
+    }
+
+    /// Run every check over a `Map`, in the order they were added.
+    pub fn run(&self, map: &Map) -> Vec<Diagnostic> {
+        self.lints
+            .iter()
+            .flat_map(|lint| {
+                let mut report = Report {
+                    lint: lint.name(),
+                    severity: lint.severity(),
+                    diagnostics: Vec::new(),
+                };
+                lint.check(map, &mut report);
+                report.diagnostics
+            })
+            .collect()
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Linter::new()
+            .with(DuplicateRoomNames)
+            .with(DuplicateEntityIds)
+            .with(NoPlayerSpawn)
+            .with(EntitiesOutOfBounds)
+            .with(TilesOutOfBounds)
+            .with(SmallRooms::default())
+            .with(InvalidElements)
+    }
+}
+
+impl fmt::Debug for Linter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+/// Run the default checks over a `Map`.
+pub fn lint(map: &Map) -> Vec<Diagnostic> {
+    Linter::default().run(map)
+}
+
+/// More than one `Level` has the same name.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DuplicateRoomNames;
+
+impl Lint for DuplicateRoomNames {
+    fn name(&self) -> &'static str {
+        "duplicate_room_names"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, map: &Map, report: &mut Report) {
+        let mut seen = HashSet::new();
+        for level in &map.levels.levels {
+            if !seen.insert(&level.name) {
+                report.level(level, "another room has the same name");
+            }
+        }
+    }
+}
+
+/// More than one `Entity` in the `Map` has the same id.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DuplicateEntityIds;
+
+impl Lint for DuplicateEntityIds {
+    fn name(&self) -> &'static str {
+        "duplicate_entity_ids"
+    }
+
+    fn check(&self, map: &Map, report: &mut Report) {
+        let mut seen = HashMap::new();
+        for level in &map.levels.levels {
+            for entity in &level.entities.entities {
+                let id = match entity.id() {
+                    Some(id) => id,
+                    None => continue,
+                };
+
+                match seen.entry(id) {
+                    Entry::Occupied(first) => {
+                        let message = format!(
+                            "`{}` has id {}, which is already used in `{}`",
+                            entity.name(),
+                            id,
+                            first.get()
+                        );
+                        report.level(level, message);
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(&level.name);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A `Level` has no `player` entity to spawn at.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoPlayerSpawn;
+
+impl Lint for NoPlayerSpawn {
+    fn name(&self) -> &'static str {
+        "no_player_spawn"
+    }
+
+    fn check(&self, map: &Map, report: &mut Report) {
+        for level in &map.levels.levels {
+            let entities = &level.entities.entities;
+            if !entities.iter().any(|entity| entity.name() == "player") {
+                report.level(level, "room has no player spawn");
+            }
+        }
+    }
+}
+
+/// An `Entity` is positioned outside of its `Level`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EntitiesOutOfBounds;
+
+impl Lint for EntitiesOutOfBounds {
+    fn name(&self) -> &'static str {
+        "entities_out_of_bounds"
+    }
+
+    fn check(&self, map: &Map, report: &mut Report) {
+        for level in &map.levels.levels {
+            let bounds = Rect::new(0, 0, level.width, level.height);
+            for entity in &level.entities.entities {
+                match entity.position() {
+                    Some((x, y)) if !bounds.contains(x, y) => {
+                        let message =
+                            format!("`{}` at ({}, {}) is outside the room", entity.name(), x, y);
+                        report.level(level, message);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// A `Level` has tiles past its right or bottom edge, which the game ignores.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TilesOutOfBounds;
+
+impl TilesOutOfBounds {
+    fn check_rows(
+        report: &mut Report,
+        level: &Level,
+        kind: &str,
+        text: &str,
+        row_width: impl Fn(&str) -> usize,
+    ) {
+        let (width, height) = level.size_in_tiles();
+        for (y, row) in text.lines().enumerate() {
+            let row_width = row_width(row);
+            if row_width > width {
+                let message = format!(
+                    "{} row {} is {} tiles wide, but the room is {}",
+                    kind, y, row_width, width
+                );
+                report.level(level, message);
+            }
+        }
+
+        let rows = text.lines().count();
+        if rows > height {
+            let message = format!(
+                "{} has {} rows, but the room is {} tiles tall",
+                kind, rows, height
+            );
+            report.level(level, message);
+        }
+    }
+}
+
+impl Lint for TilesOutOfBounds {
+    fn name(&self) -> &'static str {
+        "tiles_out_of_bounds"
+    }
+
+    fn check(&self, map: &Map, report: &mut Report) {
+        let chars = |row: &str| row.chars().count();
+        let indices = |row: &str| {
+            if row.is_empty() {
+                0
+            } else {
+                row.split(',').count()
+            }
+        };
+
+        for level in &map.levels.levels {
+            Self::check_rows(report, level, "solids", &level.solids.contents, chars);
+            Self::check_rows(report, level, "bg", &level.bg.contents, chars);
+            if let Some(objtiles) = &level.objtiles {
+                Self::check_rows(report, level, "objtiles", &objtiles.tiles, indices);
+            }
+        }
+    }
+}
+
+/// A `Level` is smaller than the screen, so the camera shows outside of it.
+#[derive(Clone, Copy, Debug)]
+pub struct SmallRooms {
+    /// The minimum width in pixels.
+    pub min_width: i32,
+    /// The minimum height in pixels.
+    pub min_height: i32,
+}
+
+/// Defaults to the size of the screen, 320x184 pixels.
+impl Default for SmallRooms {
+    fn default() -> Self {
+        SmallRooms {
+            min_width: 320,
+            min_height: 184,
+        }
+    }
+}
+
+impl Lint for SmallRooms {
+    fn name(&self) -> &'static str {
+        "small_rooms"
+    }
+
+    fn check(&self, map: &Map, report: &mut Report) {
+        for level in &map.levels.levels {
+            if level.width < self.min_width || level.height < self.min_height {
+                let message = format!(
+                    "room is {}x{}, smaller than {}x{}",
+                    level.width, level.height, self.min_width, self.min_height
+                );
+                report.level(level, message);
+            }
+        }
+    }
+}
+
+/// Elements failed to parse, and were stored in `Levels::invalid_levels` or
+/// `Level::invalid`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InvalidElements;
+
+impl Lint for InvalidElements {
+    fn name(&self) -> &'static str {
+        "invalid_elements"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, map: &Map, report: &mut Report) {
+        for elem in &map.levels.invalid_levels {
+            report.map(format!("`{}` couldn't be parsed as a room", elem.name));
+        }
+
+        for level in &map.levels.levels {
+            for elem in &level.invalid {
+                report.level(level, format!("`{}` couldn't be parsed", elem.name));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::binel::BinEl;
+    use crate::maps::entities::{Entity, Player, Refill};
+    use crate::maps::ObjTiles;
+
+    fn level(name: &str) -> Level {
+        let mut level = Level {
+            name: name.to_string(),
+            width: 320,
+            height: 184,
+            ..Level::default()
+        };
+        level.entities.entities.push(Entity::Player(Player {
+            id: 0,
+            x: 16,
+            y: 16,
+            ..Player::default()
+        }));
+        level
+    }
+
+    fn lints(map: &Map) -> Vec<&'static str> {
+        lint(map)
+            .into_iter()
+            .map(|diagnostic| diagnostic.lint)
+            .collect()
+    }
+
+    #[test]
+    fn clean_map() {
+        let mut map = Map::default();
+        map.levels.levels.push(level("a"));
+        assert_eq!(lint(&map), vec![]);
+    }
+
+    #[test]
+    fn builtin_lints() {
+        let mut a = level("a");
+        a.width = 300;
+        a.solids.contents = "1".repeat(39);
+        a.invalid.push(BinEl::new("broken"));
+
+        let mut b = level("a");
+        b.entities.entities.push(Entity::Refill(Refill {
+            id: 1,
+            x: 320,
+            y: 0,
+            ..Refill::default()
+        }));
+        b.objtiles = Some(ObjTiles {
+            tiles: "-1\n".repeat(24),
+        });
+
+        let mut map = Map::default();
+        map.levels.levels = vec![a, b];
+        map.levels.invalid_levels.push(BinEl::new("level"));
+
+        assert_eq!(
+            lints(&map),
+            vec![
+                "duplicate_room_names",
+                "duplicate_entity_ids",
+                "entities_out_of_bounds",
+                "tiles_out_of_bounds",
+                "tiles_out_of_bounds",
+                "small_rooms",
+                "invalid_elements",
+                "invalid_elements",
+            ]
+        );
+
+        let diagnostics = lint(&map);
+        assert_eq!(
+            diagnostics[3].to_string(),
+            "warning[tiles_out_of_bounds] in `a`: solids row 0 is 39 tiles wide, but the room is 38"
+        );
+    }
+
+    #[test]
+    fn custom_lint() {
+        struct NoDark;
+
+        impl Lint for NoDark {
+            fn name(&self) -> &'static str {
+                "no_dark"
+            }
+
+            fn check(&self, map: &Map, report: &mut Report) {
+                for level in map.levels.levels.iter().filter(|level| level.dark) {
+                    report.level(level, "dark rooms aren't allowed");
+                }
+            }
+        }
+
+        let mut dark = level("dark");
+        dark.dark = true;
+        let mut map = Map::default();
+        map.levels.levels = vec![level("light"), dark];
+
+        let linter = Linter::new().with(NoDark);
+        assert_eq!(linter.names().collect::<Vec<_>>(), vec!["no_dark"]);
+        assert_eq!(
+            linter.run(&map),
+            vec![Diagnostic {
+                lint: "no_dark",
+                severity: Severity::Warning,
+                level: Some("dark".to_string()),
+                message: "dark rooms aren't allowed".to_string(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,532 @@
+//! Typed Everest map metadata, stored in the `meta` element. Only the
+//! well-known fields are typed. Other attributes and children are kept as-is,
+//! and everything is written back in the order it was read.
+
+use super::attrs::*;
+use crate::binel::serialize::*;
+use crate::binel::*;
+use crate::Result;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::prelude::v1::*;
+
+/// The music and ambience playing in a `Mode` or at a `Checkpoint`.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AudioState {
+    /// The FMOD music event, such as `event:/music/lvl1/main`.
+    pub music: Option<String>,
+    /// The FMOD ambience event.
+    pub ambience: Option<String>,
+    /// All other attributes.
+    pub other: Attributes,
+    /// All other children.
+    pub children: Vec<BinEl>,
+    /// The attribute names in the order they were read. Attributes that
+    /// aren't listed are written after the rest.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub order: Vec<String>,
+}
+
+impl AudioState {
+    fn from_elem(mut elem: BinEl) -> Self {
+        let children = elem.drain().collect();
+        let mut attrs = elem.attributes;
+        AudioState {
+            order: order(&attrs),
+            music: take(&mut attrs, "Music"),
+            ambience: take(&mut attrs, "Ambience"),
+            other: attrs,
+            children,
+        }
+    }
+
+    fn into_elem(self) -> BinEl {
+        let mut elem = BinEl::new("audiostate");
+        put(&mut elem.attributes, "Music", self.music);
+        put(&mut elem.attributes, "Ambience", self.ambience);
+        finish(elem, self.other, self.children, &self.order, &[])
+    }
+}
+
+/// A checkpoint within a `Mode`.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Checkpoint {
+    /// The name of the `Level` the checkpoint is in.
+    pub level: Option<String>,
+    /// The dialog key of the checkpoint's name.
+    pub name: Option<String>,
+    pub dreaming: Option<bool>,
+    /// The player's inventory, such as `Default` or `TheSummit`.
+    pub inventory: Option<String>,
+    pub audio_state: Option<AudioState>,
+    /// All other attributes.
+    pub other: Attributes,
+    /// All other children.
+    pub children: Vec<BinEl>,
+    /// The attribute names in the order they were read. Attributes that
+    /// aren't listed are written after the rest.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub order: Vec<String>,
+    /// The child names in the order they were read. Children that aren't
+    /// listed are written after the rest.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub child_order: Vec<String>,
+}
+
+impl Checkpoint {
+    fn from_elem(mut elem: BinEl) -> Self {
+        let mut children: Vec<_> = elem.drain().collect();
+        let mut attrs = elem.attributes;
+        Checkpoint {
+            order: order(&attrs),
+            child_order: child_order(&children),
+            level: take(&mut attrs, "Level"),
+            name: take(&mut attrs, "Name"),
+            dreaming: take(&mut attrs, "Dreaming"),
+            inventory: take(&mut attrs, "Inventory"),
+            audio_state: take_child(&mut children, "audiostate").map(AudioState::from_elem),
+            other: attrs,
+            children,
+        }
+    }
+
+    fn into_elem(self) -> BinEl {
+        let mut elem = BinEl::new("checkpoint");
+        put(&mut elem.attributes, "Level", self.level);
+        put(&mut elem.attributes, "Name", self.name);
+        put(&mut elem.attributes, "Dreaming", self.dreaming);
+        put(&mut elem.attributes, "Inventory", self.inventory);
+        if let Some(audio_state) = self.audio_state {
+            elem.insert(audio_state.into_elem());
+        }
+        finish(
+            elem,
+            self.other,
+            self.children,
+            &self.order,
+            &self.child_order,
+        )
+    }
+}
+
+/// The properties of a side of the chapter.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Mode {
+    /// The map the mode uses, relative to the `Maps` directory.
+    pub path: Option<String>,
+    /// The dialog key of the poem shown when collecting the crystal heart.
+    pub poem_id: Option<String>,
+    /// The name of the `Level` the mode starts in.
+    pub start_level: Option<String>,
+    /// Whether collecting the crystal heart ends the chapter.
+    pub heart_is_end: Option<bool>,
+    /// The player's inventory, such as `Default` or `TheSummit`.
+    pub inventory: Option<String>,
+    pub audio_state: Option<AudioState>,
+    /// Stored in a `checkpoints` child.
+    pub checkpoints: Option<Vec<Checkpoint>>,
+    /// All other attributes.
+    pub other: Attributes,
+    /// All other children.
+    pub children: Vec<BinEl>,
+    /// The attribute names in the order they were read. Attributes that
+    /// aren't listed are written after the rest.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub order: Vec<String>,
+    /// The child names in the order they were read. Children that aren't
+    /// listed are written after the rest.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub child_order: Vec<String>,
+}
+
+impl Mode {
+    fn from_elem(mut elem: BinEl) -> Self {
+        let mut children: Vec<_> = elem.drain().collect();
+        let mut attrs = elem.attributes;
+        Mode {
+            order: order(&attrs),
+            child_order: child_order(&children),
+            path: take(&mut attrs, "Path"),
+            poem_id: take(&mut attrs, "PoemID"),
+            start_level: take(&mut attrs, "StartLevel"),
+            heart_is_end: take(&mut attrs, "HeartIsEnd"),
+            inventory: take(&mut attrs, "Inventory"),
+            audio_state: take_child(&mut children, "audiostate").map(AudioState::from_elem),
+            checkpoints: take_list(&mut children, "checkpoints", "checkpoint")
+                .map(|list| list.into_iter().map(Checkpoint::from_elem).collect()),
+            other: attrs,
+            children,
+        }
+    }
+
+    fn into_elem(self) -> BinEl {
+        let mut elem = BinEl::new("mode");
+        put(&mut elem.attributes, "Path", self.path);
+        put(&mut elem.attributes, "PoemID", self.poem_id);
+        put(&mut elem.attributes, "StartLevel", self.start_level);
+        put(&mut elem.attributes, "HeartIsEnd", self.heart_is_end);
+        put(&mut elem.attributes, "Inventory", self.inventory);
+        if let Some(audio_state) = self.audio_state {
+            elem.insert(audio_state.into_elem());
+        }
+        if let Some(checkpoints) = self.checkpoints {
+            let mut list = BinEl::new("checkpoints");
+            for checkpoint in checkpoints {
+                list.insert(checkpoint.into_elem());
+            }
+            elem.insert(list);
+        }
+        finish(
+            elem,
+            self.other,
+            self.children,
+            &self.order,
+            &self.child_order,
+        )
+    }
+}
+
+/// How the chapter appears on the overworld mountain.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Mountain {
+    /// The directory of custom mountain models.
+    pub model_directory: Option<String>,
+    /// The directory of custom mountain textures.
+    pub texture_directory: Option<String>,
+    /// The FMOD music event.
+    pub background_music: Option<String>,
+    /// The FMOD ambience event.
+    pub background_ambience: Option<String>,
+    pub show_core: Option<bool>,
+    pub show_snow: Option<bool>,
+    /// The mountain's lighting, from 0 to 3.
+    pub state: Option<i32>,
+    pub rotate: Option<bool>,
+    /// All other attributes.
+    pub other: Attributes,
+    /// All other children, such as the camera positions.
+    pub children: Vec<BinEl>,
+    /// The attribute names in the order they were read. Attributes that
+    /// aren't listed are written after the rest.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub order: Vec<String>,
+}
+
+impl Mountain {
+    fn from_elem(mut elem: BinEl) -> Self {
+        let children = elem.drain().collect();
+        let mut attrs = elem.attributes;
+        Mountain {
+            order: order(&attrs),
+            model_directory: take(&mut attrs, "MountainModelDirectory"),
+            texture_directory: take(&mut attrs, "MountainTextureDirectory"),
+            background_music: take(&mut attrs, "BackgroundMusic"),
+            background_ambience: take(&mut attrs, "BackgroundAmbience"),
+            show_core: take(&mut attrs, "ShowCore"),
+            show_snow: take(&mut attrs, "ShowSnow"),
+            state: take(&mut attrs, "State"),
+            rotate: take(&mut attrs, "Rotate"),
+            other: attrs,
+            children,
+        }
+    }
+
+    fn into_elem(self) -> BinEl {
+        let mut elem = BinEl::new("mountain");
+        let attrs = &mut elem.attributes;
+        put(attrs, "MountainModelDirectory", self.model_directory);
+        put(attrs, "MountainTextureDirectory", self.texture_directory);
+        put(attrs, "BackgroundMusic", self.background_music);
+        put(attrs, "BackgroundAmbience", self.background_ambience);
+        put(attrs, "ShowCore", self.show_core);
+        put(attrs, "ShowSnow", self.show_snow);
+        put(attrs, "State", self.state);
+        put(attrs, "Rotate", self.rotate);
+        finish(elem, self.other, self.children, &self.order, &[])
+    }
+}
+
+/// Everest extension, for storing the `Map`'s name, icon and other
+/// chapter-wide settings.
+///
+/// # Examples:
+/// ```
+/// use celeste::binel::{serialize::*, *};
+/// use celeste::maps::Meta;
+///
+/// let elem = BinEl::build("meta")
+///     .attr("Icon", "areas/mymod/icon")
+///     .attr("Interlude", false)
+///     .attr("TitleBaseColor", "6c7c81")
+///     .child(
+///         BinEl::build("mode").attr("StartLevel", "a-00").child(
+///             BinEl::build("checkpoints")
+///                 .child(BinEl::build("checkpoint").attr("Level", "b-00").attr("Name", "mymod_cp1")),
+///         ),
+///     )
+///     .finish();
+///
+/// let meta = Meta::from_binel(BinElValue::Element(elem.clone())).unwrap();
+/// assert_eq!(meta.icon.as_deref(), Some("areas/mymod/icon"));
+/// let checkpoints = meta.modes[0].checkpoints.as_ref().unwrap();
+/// assert_eq!(checkpoints[0].level.as_deref(), Some("b-00"));
+/// assert!(meta.other.contains_key("TitleBaseColor"));
+///
+/// match meta.into_binel() {
+///     BinElValue::Element(written) => assert_eq!(written, elem),
+///     _ => panic!("Didn't get element!"),
+/// }
+/// ```
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Meta {
+    /// The chapter icon, within the Gui atlas.
+    pub icon: Option<String>,
+    /// Whether the chapter is an interlude, like Prologue or Epilogue.
+    pub interlude: Option<bool>,
+    /// Whether the chapter starts dreaming, as in Old Site.
+    pub dreaming: Option<bool>,
+    /// The default color grade.
+    pub color_grade: Option<String>,
+    /// The screen wipe used on death and transitions.
+    pub wipe: Option<String>,
+    /// How the player enters the chapter, such as `WalkInRight` or `WakeUp`.
+    pub intro_type: Option<String>,
+    /// The FMOD event played in cassette rooms.
+    pub cassette_song: Option<String>,
+    pub mountain: Option<Mountain>,
+    /// Stored as `mode` children, one per side.
+    pub modes: Vec<Mode>,
+    /// All other attributes.
+    pub other: Attributes,
+    /// All other children, such as `cassettemodifier`.
+    pub children: Vec<BinEl>,
+    /// The attribute names in the order they were read. Attributes that
+    /// aren't listed are written after the rest.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub order: Vec<String>,
+    /// The child names in the order they were read. Children that aren't
+    /// listed are written after the rest.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub child_order: Vec<String>,
+}
+
+// A list stored as a child element, only if it holds nothing else.
+fn take_list(children: &mut Vec<BinEl>, name: &str, item: &str) -> Option<Vec<BinEl>> {
+    let index = children.iter().position(|child| {
+        child.name == name
+            && child.attributes.is_empty()
+            && child.children().all(|child| child.name == item)
+    })?;
+    Some(children.remove(index).drain().collect())
+}
+
+// Add the untyped attributes and children after the typed ones, then sort
+// everything back into the order it was read.
+fn finish(
+    mut elem: BinEl,
+    other: Attributes,
+    children: Vec<BinEl>,
+    order: &[String],
+    child_order: &[String],
+) -> BinEl {
+    elem.attributes.extend(other);
+    reorder_attributes(&mut elem.attributes, order);
+    let mut all: Vec<_> = elem.drain().chain(children).collect();
+    reorder_children(&mut all, child_order);
+    for child in all {
+        elem.insert(child);
+    }
+    elem
+}
+
+impl BinElType for Meta {
+    fn into_binel(self) -> BinElValue {
+        let mut elem = BinEl::new("meta");
+        let attrs = &mut elem.attributes;
+        put(attrs, "Icon", self.icon);
+        put(attrs, "Interlude", self.interlude);
+        put(attrs, "Dreaming", self.dreaming);
+        put(attrs, "ColorGrade", self.color_grade);
+        put(attrs, "Wipe", self.wipe);
+        put(attrs, "IntroType", self.intro_type);
+        put(attrs, "CassetteSong", self.cassette_song);
+        for mode in self.modes {
+            elem.insert(mode.into_elem());
+        }
+        if let Some(mountain) = self.mountain {
+            elem.insert(mountain.into_elem());
+        }
+        BinElValue::Element(finish(
+            elem,
+            self.other,
+            self.children,
+            &self.order,
+            &self.child_order,
+        ))
+    }
+
+    fn from_binel(binel: BinElValue) -> Result<'static, Self> {
+        let mut elem = BinEl::from_binel(binel)?;
+        if elem.name != "meta" {
+            return Err(crate::Error::deserialize(
+                "element `meta`",
+                format!("element `{}`", elem.name),
+            ));
+        }
+
+        let mut children: Vec<_> = elem.drain().collect();
+        let child_order = child_order(&children);
+        let mut modes = Vec::new();
+        while let Some(mode) = take_child(&mut children, "mode") {
+            modes.push(Mode::from_elem(mode));
+        }
+
+        let mut attrs = elem.attributes;
+        Ok(Meta {
+            order: order(&attrs),
+            child_order,
+            icon: take(&mut attrs, "Icon"),
+            interlude: take(&mut attrs, "Interlude"),
+            dreaming: take(&mut attrs, "Dreaming"),
+            color_grade: take(&mut attrs, "ColorGrade"),
+            wipe: take(&mut attrs, "Wipe"),
+            intro_type: take(&mut attrs, "IntroType"),
+            cassette_song: take(&mut attrs, "CassetteSong"),
+            mountain: take_child(&mut children, "mountain").map(Mountain::from_elem),
+            modes,
+            other: attrs,
+            children,
+        })
+    }
+
+    fn maybe_attr() -> bool {
+        false
+    }
+
+    fn elem_name() -> Option<&'static str> {
+        Some("meta")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_unknown() {
+        let elem = BinEl::build("meta")
+            .attr("Wipe", "Celeste.CurtainWipe")
+            .attr("Interlude", 1)
+            .child(
+                BinEl::build("mode")
+                    .attr("HeartIsEnd", true)
+                    .child(BinEl::build("audiostate").attr("Music", "event:/music/lvl1/main"))
+                    .child(BinEl::build("checkpoints").attr("Unknown", true)),
+            )
+            .child(
+                BinEl::build("mountain")
+                    .attr("State", 2)
+                    .child(BinEl::new("idle")),
+            )
+            .child(BinEl::build("cassettemodifier").attr("TempoMult", 1.5))
+            .finish();
+
+        let meta = Meta::from_binel(BinElValue::Element(elem.clone())).unwrap();
+        assert_eq!(meta.interlude, None);
+        assert_eq!(meta.other.get("Interlude"), Some(&BinElAttr::Int(1)));
+
+        let mode = &meta.modes[0];
+        let music = mode
+            .audio_state
+            .as_ref()
+            .and_then(|state| state.music.as_deref());
+        assert_eq!(music, Some("event:/music/lvl1/main"));
+        assert_eq!(mode.checkpoints, None);
+        assert_eq!(mode.children.len(), 1);
+
+        let mountain = meta.mountain.as_ref().unwrap();
+        assert_eq!(mountain.state, Some(2));
+        assert_eq!(mountain.children, vec![BinEl::new("idle")]);
+        assert_eq!(meta.children.len(), 1);
+
+        match meta.into_binel() {
+            BinElValue::Element(written) => assert_eq!(written, elem),
+            _ => panic!("Didn't get element!"),
+        }
+    }
+
+    #[test]
+    fn keeps_order() {
+        let elem = BinEl::build("meta")
+            .attr("TitleBaseColor", "6c7c81")
+            .attr("Wipe", "Celeste.CurtainWipe")
+            .attr("Icon", "areas/mymod/icon")
+            .child(BinEl::build("cassettemodifier").attr("TempoMult", 1.5))
+            .child(
+                BinEl::build("mountain")
+                    .attr("State", 2)
+                    .attr("ShowCore", true),
+            )
+            .child(
+                BinEl::build("mode")
+                    .attr("Inventory", "Default")
+                    .attr("Path", "mymod/1-a")
+                    .child(BinEl::new("unknown"))
+                    .child(
+                        BinEl::build("checkpoints").child(
+                            BinEl::build("checkpoint")
+                                .attr("Name", "mymod_cp1")
+                                .attr("Level", "b-00"),
+                        ),
+                    )
+                    .child(BinEl::build("audiostate").attr("Music", "event:/music/lvl1/main")),
+            )
+            .child(BinEl::new("mode"))
+            .finish();
+
+        let meta = Meta::from_binel(BinElValue::Element(elem.clone())).unwrap();
+        assert_eq!(meta.modes.len(), 2);
+        let written = match meta.into_binel() {
+            BinElValue::Element(written) => written,
+            _ => panic!("Didn't get element!"),
+        };
+        assert_eq!(written, elem);
+
+        // equality ignores the order of attributes
+        fn keys(elem: &BinEl) -> Vec<Vec<&String>> {
+            let mut all = vec![elem.attributes.keys().collect()];
+            all.extend(elem.children().flat_map(keys));
+            all
+        }
+        assert_eq!(keys(&written), keys(&elem));
+    }
+}
@@ -2,30 +2,107 @@
 
 use crate::binel::serialize::*;
 use crate::binel::*;
+use crate::Result;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::prelude::v1::*;
 
+mod attrs;
+use attrs::Attributes;
+
+pub mod entities;
+use entities::Entity;
+
+pub mod triggers;
+use triggers::Trigger;
+
+pub mod stylegrounds;
+use stylegrounds::Styleground;
+
+pub mod meta;
+pub use meta::Meta;
+
+pub mod tiles;
+use tiles::TileGrid;
+
+pub mod geometry;
+use entities::Direction;
+use geometry::Rect;
+
+pub mod lint;
+
+#[cfg(feature = "render")]
+pub mod render;
+
+#[cfg(feature = "xml")]
+pub mod autotile;
+
 /// A `Level`'s "stylegrounds," or complexly animated backgrounds.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[celeste_name = "Style"]
 pub struct Stylegrounds {
     pub foregrounds: Foregrounds,
-    pub backgrounds: Backgrounds, // TODO: implement stylegrounds
+    pub backgrounds: Backgrounds,
+    /// All other children.
+    #[celeste_child_vec]
+    pub children: Vec<BinEl>,
+    /// All other attributes.
+    #[celeste_other]
+    pub other: Attributes,
+    /// The attribute names in the order they were read. Attributes that
+    /// aren't listed are written after the rest.
+    #[celeste_order]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub order: Vec<String>,
+    /// The child names in the order they were read. Children that aren't
+    /// listed are written after the rest.
+    #[celeste_child_order]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub child_order: Vec<String>,
 }
 
-/// Foreground stylegrounds. Currently not deserialized, instead just being a
-/// newtype over `BinEl`.
+/// Foreground stylegrounds, drawn in front of the `Level`.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[celeste_name = "Foregrounds"]
-pub struct Foregrounds(pub BinEl);
+pub struct Foregrounds {
+    #[celeste_child_vec]
+    pub layers: Vec<Styleground>,
+}
 
-/// Background stylegrounds. Currently not deserialized, instead just being a
-/// newtype over `BinEl`.
+impl Foregrounds {
+    /// The layers with all `apply` groups flattened.
+    pub fn resolved(&self) -> Vec<Styleground> {
+        stylegrounds::resolve(&self.layers)
+    }
+}
+
+/// Background stylegrounds, drawn behind the `Level`.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[celeste_name = "Backgrounds"]
-pub struct Backgrounds(pub BinEl);
+pub struct Backgrounds {
+    #[celeste_child_vec]
+    pub layers: Vec<Styleground>,
+}
+
+impl Backgrounds {
+    /// The layers with all `apply` groups flattened.
+    pub fn resolved(&self) -> Vec<Styleground> {
+        stylegrounds::resolve(&self.layers)
+    }
+}
 
 /// The tilesets used in the `Level`'s background.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[celeste_name = "bgtiles"]
 pub struct BGTiles {
     /// The tileset. Seems to always be "Scenery," but this may change.
@@ -34,6 +111,7 @@ pub struct BGTiles {
 
 /// The tilesets used in the `Level`'s foreground.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[celeste_name = "fgtiles"]
 pub struct FGTiles {
     /// The tileset. Seems to always be "Scenery," but this may change.
@@ -42,22 +120,49 @@ pub struct FGTiles {
 
 /// The solid tiles in the `Level`'s foreground.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Solids {
     /// The actual tiles, stored as a string.
     #[celeste_name = "innerText"]
     pub contents: String,
 }
 
+impl Solids {
+    /// Parse the tiles into a grid of the given size in tiles.
+    pub fn grid(&self, width: usize, height: usize) -> TileGrid<char> {
+        TileGrid::parse(&self.contents, width, height).expect("tilesets always parse")
+    }
+
+    /// Replace the tiles with a grid.
+    pub fn set_grid(&mut self, grid: &TileGrid<char>) {
+        self.contents = grid.to_string();
+    }
+}
+
 /// The tiles in the `Level`'s background.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[celeste_name = "bg"]
 pub struct BGSolids {
     #[celeste_name = "innerText"]
     pub contents: String,
 }
 
+impl BGSolids {
+    /// Parse the tiles into a grid of the given size in tiles.
+    pub fn grid(&self, width: usize, height: usize) -> TileGrid<char> {
+        TileGrid::parse(&self.contents, width, height).expect("tilesets always parse")
+    }
+
+    /// Replace the tiles with a grid.
+    pub fn set_grid(&mut self, grid: &TileGrid<char>) {
+        self.contents = grid.to_string();
+    }
+}
+
 /// Decals, or image assets in a `Level`.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Decal {
     /// The pixel (8 per tile) location in the `Level`.
     pub x: i32,
@@ -71,8 +176,16 @@ pub struct Decal {
     pub texture: String,
 }
 
+impl Decal {
+    /// The `Decal`'s location in world space, given the `Level` it is in.
+    pub fn world_position(&self, level: &Level) -> (i32, i32) {
+        level.to_world(self.x, self.y)
+    }
+}
+
 /// Background decals, or image assets in a `Level`.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[celeste_name = "bgdecals"]
 pub struct BGDecals {
     /// The "tileset". Seems to always be "Scenery," but this may change.
@@ -85,6 +198,7 @@ pub struct BGDecals {
 
 /// Foreground decals, or image assets in a `Level`.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[celeste_name = "fgdecals"]
 pub struct FGDecals {
     /// The "tileset". Seems to always be "Scenery," but this may change.
@@ -97,44 +211,80 @@ pub struct FGDecals {
 
 /// Entities, or objects in the `Level` with associated code.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Entities {
-    /// The actual entities. May be one of over 100 elements, so currently
-    /// parsed as a raw BinEl.
+    /// The actual entities. May be one of over 100 elements, so only the
+    /// common vanilla ones are typed.
     #[celeste_child_vec]
-    pub entities: Vec<BinEl>,
+    pub entities: Vec<Entity>,
 }
 
 /// Triggers, or regions in the `Level` with associated code.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Triggers {
-    /// The actual triggers. May be one of over 50 elements, so currently parsed
-    /// as a raw BinEl.
+    /// The actual triggers. May be one of over 50 elements, so only the common
+    /// vanilla ones are typed.
     #[celeste_child_vec]
-    pub triggers: Vec<BinEl>,
+    pub triggers: Vec<Trigger>,
 }
 
 /// Object tiles. Poorly documented.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ObjTiles {
     #[celeste_name = "innerText"]
     pub tiles: String,
 }
 
+impl ObjTiles {
+    /// Parse the tiles into a grid of the given size in tiles.
+    pub fn grid(&self, width: usize, height: usize) -> Result<'static, TileGrid<i32>> {
+        TileGrid::parse(&self.tiles, width, height)
+    }
+
+    /// Replace the tiles with a grid.
+    pub fn set_grid(&mut self, grid: &TileGrid<i32>) {
+        self.tiles = grid.to_string();
+    }
+}
+
+/// A rectangle of solid tiles outside of any `Level`, measured in tiles (8
+/// pixels).
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[celeste_name = "rect"]
+pub struct FillerRect {
+    pub x: i32,
+    pub y: i32,
+    /// The width.
+    pub w: i32,
+    /// The height.
+    pub h: i32,
+}
+
+impl FillerRect {
+    /// Whether the rectangle covers a point in world space, measured in pixels.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let (x, y) = (x.div_euclid(8), y.div_euclid(8));
+        x >= self.x && x < self.x + self.w && y >= self.y && y < self.y + self.h
+    }
+}
+
 /// Filler regions in the map. An alternate way of storing rooms filled with a
-/// single tile and no other assets. Not currently parsed, as they are not
-/// necessary for most use cases. The `Map`'s behavior shouldn't change if you
-/// remove these.
+/// single tile and no other assets. The `Map`'s behavior shouldn't change if
+/// you remove these.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[celeste_name = "Filler"]
-pub struct Filler(pub BinEl); // TODO: parse filler
-
-/// Undocumented (apart from source) Everest extension, for storing the `Map`'s
-/// name and icon.
-#[derive(Clone, PartialEq, Debug, Default, BinElType)]
-pub struct Meta(pub BinEl);
+pub struct Filler {
+    #[celeste_child_vec]
+    pub rects: Vec<FillerRect>,
+}
 
 /// A room in a `Map`. Only confusingly named fields are documented.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Level {
     pub name: String,
     pub x: i32,
@@ -174,21 +324,50 @@ pub struct Level {
     /// All children that failed to parse.
     #[celeste_child_vec]
     pub invalid: Vec<BinEl>,
+    /// All other attributes.
+    #[celeste_other]
+    pub other: Attributes,
+    /// The attribute names in the order they were read. Attributes that
+    /// aren't listed are written after the rest.
+    #[celeste_order]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub order: Vec<String>,
+    /// The child names in the order they were read. Children that aren't
+    /// listed are written after the rest.
+    #[celeste_child_order]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub child_order: Vec<String>,
 }
 
 /// All `Level`s in a `Map`. Stored as a subelement for unknown reasons.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Levels {
     #[celeste_child_vec]
     pub levels: Vec<Level>,
     /// All children that couldn't be parsed as `Level`s.
     #[celeste_child_vec]
     pub invalid_levels: Vec<BinEl>,
+    /// The child names in the order they were read. Children that aren't
+    /// listed are written after the rest.
+    #[celeste_child_order]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub child_order: Vec<String>,
 }
 
 /// A chapter, also known as an Area in the game's code. `Map` was chosen to
 /// avoid confusion. Parsed via the `BinElType` trait.
 #[derive(Clone, PartialEq, Debug, Default, BinElType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[celeste_name = "Map"]
 pub struct Map {
     pub style: Stylegrounds,
@@ -197,4 +376,262 @@ pub struct Map {
     /// Optional, as it is an Everest extension, and thus many maps do not
     /// include it.
     pub meta: Option<Meta>,
+    /// All other children.
+    #[celeste_child_vec]
+    pub children: Vec<BinEl>,
+    /// All other attributes.
+    #[celeste_other]
+    pub other: Attributes,
+    /// The attribute names in the order they were read. Attributes that
+    /// aren't listed are written after the rest.
+    #[celeste_order]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub order: Vec<String>,
+    /// The child names in the order they were read. Children that aren't
+    /// listed are written after the rest.
+    #[celeste_child_order]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub child_order: Vec<String>,
+}
+
+impl Level {
+    /// The width and height in tiles, counting partial tiles.
+    pub fn size_in_tiles(&self) -> (usize, usize) {
+        let tiles = |pixels: i32| (pixels.max(0) as usize).div_ceil(8);
+        (tiles(self.width), tiles(self.height))
+    }
+
+    /// The foreground tiles, sized to the `Level`.
+    pub fn solids_grid(&self) -> TileGrid<char> {
+        let (width, height) = self.size_in_tiles();
+        self.solids.grid(width, height)
+    }
+
+    /// The background tiles, sized to the `Level`.
+    pub fn bg_grid(&self) -> TileGrid<char> {
+        let (width, height) = self.size_in_tiles();
+        self.bg.grid(width, height)
+    }
+
+    /// The object tiles, sized to the `Level`. Empty if the `Level` has none.
+    pub fn objtiles_grid(&self) -> Result<'static, TileGrid<i32>> {
+        let (width, height) = self.size_in_tiles();
+        match &self.objtiles {
+            Some(objtiles) => objtiles.grid(width, height),
+            None => Ok(TileGrid::new(width, height)),
+        }
+    }
+
+    /// The area the `Level` covers in world space, measured in pixels.
+    pub fn bounds(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
+
+    /// Whether the `Level` covers a point in world space, measured in pixels.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        self.bounds().contains(x, y)
+    }
+
+    /// Convert a point relative to the `Level`, such as an `Entity`'s
+    /// position, into world space.
+    pub fn to_world(&self, x: i32, y: i32) -> (i32, i32) {
+        (x.saturating_add(self.x), y.saturating_add(self.y))
+    }
+
+    /// Convert a point in world space into one relative to the `Level`.
+    pub fn to_room(&self, x: i32, y: i32) -> (i32, i32) {
+        (x.saturating_sub(self.x), y.saturating_sub(self.y))
+    }
+
+    /// The world space location of an `Entity` in this `Level`, if it has a
+    /// position.
+    pub fn entity_world_position(&self, entity: &Entity) -> Option<(i32, i32)> {
+        let (x, y) = entity.position()?;
+        Some(self.to_world(x, y))
+    }
+}
+
+impl Map {
+    /// Whether a point in world space, measured in pixels, is covered by a
+    /// `Level` or a `FillerRect`.
+    ///
+    /// # Examples:
+    /// ```
+    /// use celeste::maps::*;
+    ///
+    /// let mut map = Map::default();
+    /// map.levels.levels.push(Level {
+    ///     width: 320,
+    ///     height: 184,
+    ///     ..Level::default()
+    /// });
+    /// map.filler.rects.push(FillerRect { x: 40, y: 0, w: 2, h: 2 });
+    ///
+    /// assert!(map.covers(319, 183));
+    /// assert!(map.covers(335, 15));
+    /// assert!(!map.covers(336, 0));
+    /// assert!(!map.covers(-1, 0));
+    /// ```
+    pub fn covers(&self, x: i32, y: i32) -> bool {
+        self.level_at(x, y).is_some() || self.filler.rects.iter().any(|rect| rect.contains(x, y))
+    }
+
+    /// The first `Level` covering a point in world space, measured in pixels.
+    pub fn level_at(&self, x: i32, y: i32) -> Option<&Level> {
+        self.levels.levels.iter().find(|level| level.contains(x, y))
+    }
+
+    /// The `Level`s sharing part of an edge with the `Level` at `index` in
+    /// `levels.levels`, as indices along with the side they are on. Empty if
+    /// `index` is out of range.
+    ///
+    /// # Examples:
+    /// ```
+    /// use celeste::maps::entities::Direction;
+    /// use celeste::maps::*;
+    ///
+    /// let room = |x, y| Level {
+    ///     x,
+    ///     y,
+    ///     width: 320,
+    ///     height: 184,
+    ///     ..Level::default()
+    /// };
+    ///
+    /// let mut map = Map::default();
+    /// map.levels.levels = vec![room(0, 0), room(320, 0), room(0, -184), room(320, 184)];
+    ///
+    /// assert_eq!(map.neighbors(0), vec![(1, Direction::Right), (2, Direction::Up)]);
+    /// assert_eq!(map.adjacent_levels(), vec![(0, 1), (0, 2), (1, 3)]);
+    /// ```
+    pub fn neighbors(&self, index: usize) -> Vec<(usize, Direction)> {
+        let levels = &self.levels.levels;
+        let bounds = match levels.get(index) {
+            Some(level) => level.bounds(),
+            None => return Vec::new(),
+        };
+
+        levels
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != index)
+            .filter_map(|(i, level)| Some((i, bounds.adjacent_side(&level.bounds())?)))
+            .collect()
+    }
+
+    /// Every pair of `Level`s sharing part of an edge, as indices into
+    /// `levels.levels` with the lower index first. Together these form the
+    /// graph of room transitions.
+    pub fn adjacent_levels(&self) -> Vec<(usize, usize)> {
+        self.level_pairs(|a, b| a.adjacent_side(b).is_some())
+    }
+
+    /// Every pair of `Level`s that overlap, as indices into `levels.levels`
+    /// with the lower index first.
+    pub fn overlapping_levels(&self) -> Vec<(usize, usize)> {
+        self.level_pairs(Rect::overlaps)
+    }
+
+    fn level_pairs(&self, matches: impl Fn(&Rect, &Rect) -> bool) -> Vec<(usize, usize)> {
+        let bounds: Vec<Rect> = self.levels.levels.iter().map(Level::bounds).collect();
+        let mut pairs = Vec::new();
+        for (i, a) in bounds.iter().enumerate() {
+            for (j, b) in bounds.iter().enumerate().skip(i + 1) {
+                if matches(a, b) {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn filler_roundtrip() {
+        let filler = BinEl::build("Filler")
+            .child(
+                BinEl::build("rect")
+                    .attr("x", -4)
+                    .attr("y", 2)
+                    .attr("w", 3)
+                    .attr("h", 1),
+            )
+            .finish();
+
+        let parsed = Filler::from_binel(BinElValue::Element(filler.clone())).unwrap();
+        assert_eq!(
+            parsed.rects,
+            vec![FillerRect {
+                x: -4,
+                y: 2,
+                w: 3,
+                h: 1
+            }]
+        );
+        assert!(parsed.rects[0].contains(-32, 16));
+        assert!(!parsed.rects[0].contains(-33, 16));
+
+        match parsed.into_binel() {
+            BinElValue::Element(elem) => assert_eq!(elem, filler),
+            _ => panic!("Didn't get element!"),
+        }
+    }
+
+    #[test]
+    fn level_geometry() {
+        let mut map = Map::default();
+        map.levels.levels = vec![
+            Level {
+                name: "a".into(),
+                x: 0,
+                y: 0,
+                width: 320,
+                height: 184,
+                ..Level::default()
+            },
+            Level {
+                name: "b".into(),
+                x: 160,
+                y: 184,
+                width: 320,
+                height: 184,
+                ..Level::default()
+            },
+            Level {
+                name: "c".into(),
+                x: 400,
+                y: 300,
+                width: 320,
+                height: 184,
+                ..Level::default()
+            },
+        ];
+
+        assert_eq!(map.level_at(200, 184).map(|level| &*level.name), Some("b"));
+        assert_eq!(map.level_at(-1, 0), None);
+        assert_eq!(map.neighbors(1), vec![(0, Direction::Up)]);
+        assert_eq!(map.neighbors(3), vec![]);
+        assert_eq!(map.adjacent_levels(), vec![(0, 1)]);
+        assert_eq!(map.overlapping_levels(), vec![(1, 2)]);
+
+        let level = &map.levels.levels[1];
+        assert_eq!(level.to_world(8, 16), (168, 200));
+        assert_eq!(level.to_room(168, 200), (8, 16));
+        let decal = Decal {
+            x: 4,
+            y: -4,
+            ..Decal::default()
+        };
+        assert_eq!(decal.world_position(level), (164, 180));
+    }
 }
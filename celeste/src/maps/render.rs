@@ -0,0 +1,582 @@
+//! A software renderer for map previews, enabled by the `render` feature. It
+//! draws the layout of a `Map` rather than its textures: rooms, tiles as
+//! colored cells, and entities and triggers as labelled rectangles.
+//!
+//! # Examples:
+//! ```
+//! use celeste::maps::render::*;
+//! use celeste::maps::*;
+//!
+//! let mut map = Map::default();
+//! map.levels.levels.push(Level {
+//!     name: "a-00".to_string(),
+//!     width: 320,
+//!     height: 184,
+//!     ..Level::default()
+//! });
+//!
+//! let options = RenderOptions {
+//!     scale: 0.5,
+//!     ..RenderOptions::default()
+//! };
+//! let image = render(&map, &options).unwrap();
+//! assert_eq!((image.width(), image.height()), (160, 92));
+//!
+//! let mut png = Vec::new();
+//! image.write_png(&mut png).unwrap();
+//! ```
+
+use super::geometry::Rect;
+use super::{Level, Map};
+use crate::{Error, Result};
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::prelude::v1::*;
+
+/// An RGBA color, without premultiplied alpha.
+pub type Color = [u8; 4];
+
+const CLEAR: Color = [24, 24, 32, 255];
+const ROOM: Color = [48, 48, 64, 255];
+const ROOM_OUTLINE: Color = [220, 220, 230, 255];
+const FILLER: Color = [96, 96, 112, 255];
+const ENTITY: Color = [230, 80, 80, 160];
+const ENTITY_OUTLINE: Color = [255, 120, 120, 255];
+const TRIGGER: Color = [80, 160, 230, 96];
+const TRIGGER_OUTLINE: Color = [120, 190, 255, 255];
+const LABEL: Color = [255, 255, 255, 255];
+
+/// Tile colors, chosen by tileset.
+const TILESETS: [Color; 8] = [
+    [198, 160, 110, 255],
+    [140, 180, 120, 255],
+    [150, 150, 170, 255],
+    [200, 130, 160, 255],
+    [120, 160, 200, 255],
+    [210, 190, 100, 255],
+    [170, 120, 90, 255],
+    [130, 200, 190, 255],
+];
+
+/// Which parts of a `Map` to draw.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Layers {
+    pub rooms: bool,
+    pub fillers: bool,
+    /// Tiles from `BGSolids`.
+    pub background: bool,
+    /// Tiles from `Solids`.
+    pub solids: bool,
+    pub entities: bool,
+    pub triggers: bool,
+    /// Names of rooms, entities and triggers.
+    pub labels: bool,
+}
+
+/// Every layer is drawn by default.
+impl Default for Layers {
+    fn default() -> Self {
+        Layers {
+            rooms: true,
+            fillers: true,
+            background: true,
+            solids: true,
+            entities: true,
+            triggers: true,
+            labels: true,
+        }
+    }
+}
+
+/// Options for `render`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RenderOptions {
+    /// Image pixels per map pixel. A scale of `0.125` draws each tile as one
+    /// pixel.
+    pub scale: f32,
+    pub layers: Layers,
+    /// The largest width or height `render` will create an image with.
+    pub max_size: u32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            scale: 1.0,
+            layers: Layers::default(),
+            max_size: 8192,
+        }
+    }
+}
+
+/// An RGBA image, stored row by row.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Create an image filled with a color.
+    pub fn new(width: u32, height: u32, color: Color) -> Self {
+        let len = width as usize * height as usize;
+        Image {
+            width,
+            height,
+            pixels: color.iter().copied().cycle().take(len * 4).collect(),
+        }
+    }
+
+    /// The width in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The RGBA bytes of every pixel, row by row.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Get a pixel, if it is inside the image.
+    pub fn get(&self, x: u32, y: u32) -> Option<Color> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        let mut color = [0; 4];
+        color.copy_from_slice(&self.pixels[i..i + 4]);
+        Some(color)
+    }
+
+    /// Draw a pixel over the image, blending by its alpha. Pixels outside the
+    /// image are ignored.
+    pub fn blend(&mut self, x: i64, y: i64, color: Color) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        let dst = &mut self.pixels[i..i + 4];
+
+        let alpha = color[3] as u32;
+        let dst_alpha = dst[3] as u32 * (255 - alpha) / 255;
+        let out_alpha = alpha + dst_alpha;
+        if out_alpha == 0 {
+            dst.copy_from_slice(&[0; 4]);
+            return;
+        }
+        for channel in 0..3 {
+            let mixed = color[channel] as u32 * alpha + dst[channel] as u32 * dst_alpha;
+            dst[channel] = (mixed / out_alpha) as u8;
+        }
+        dst[3] = out_alpha as u8;
+    }
+
+    /// Fill a rectangle, clipped to the image.
+    pub fn fill_rect(&mut self, x0: i64, y0: i64, x1: i64, y1: i64, color: Color) {
+        let (x0, x1) = (x0.max(0), x1.min(self.width as i64));
+        let (y0, y1) = (y0.max(0), y1.min(self.height as i64));
+        for y in y0..y1 {
+            for x in x0..x1 {
+                self.blend(x, y, color);
+            }
+        }
+    }
+
+    /// Draw a one pixel outline just inside a rectangle.
+    pub fn outline_rect(&mut self, x0: i64, y0: i64, x1: i64, y1: i64, color: Color) {
+        if x1 <= x0 || y1 <= y0 {
+            return;
+        }
+        self.fill_rect(x0, y0, x1, y0 + 1, color);
+        if y1 - 1 > y0 {
+            self.fill_rect(x0, y1 - 1, x1, y1, color);
+        }
+        self.fill_rect(x0, y0 + 1, x0 + 1, y1 - 1, color);
+        if x1 - 1 > x0 {
+            self.fill_rect(x1 - 1, y0 + 1, x1, y1 - 1, color);
+        }
+    }
+
+    /// Draw text in a small built-in font, with its top left corner at a
+    /// point. Text is clipped to `clip_x1` on the right, and letters are case
+    /// insensitive.
+    pub fn draw_text(&mut self, x: i64, y: i64, clip_x1: i64, text: &str, color: Color) {
+        for (i, c) in text.chars().enumerate() {
+            let left = x + i as i64 * 4;
+            if left + 3 > clip_x1 {
+                break;
+            }
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for col in 0..3 {
+                    if bits & (0b100 >> col) != 0 {
+                        self.blend(left + col, y + row as i64, color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Encode the image as a PNG.
+    pub fn write_png(&self, writer: impl Write) -> Result<'static, ()> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .map_err(|err| match err {
+                png::EncodingError::IoError(err) => err.into(),
+                err => Error::io(io::ErrorKind::InvalidInput, err.to_string()),
+            })
+    }
+}
+
+/// Maps world space onto the image.
+struct View {
+    origin_x: i32,
+    origin_y: i32,
+    scale: f32,
+}
+
+impl View {
+    fn point(&self, x: i32, y: i32) -> (i64, i64) {
+        let scale = |pixels: i64| (pixels as f32 * self.scale).floor() as i64;
+        (
+            scale(x as i64 - self.origin_x as i64),
+            scale(y as i64 - self.origin_y as i64),
+        )
+    }
+
+    /// The image pixels covered by a rectangle. Rectangles with an area are
+    /// always at least one pixel, so they don't disappear at small scales.
+    fn rect(&self, rect: Rect) -> (i64, i64, i64, i64) {
+        let (x0, y0) = self.point(rect.x, rect.y);
+        let (x1, y1) = self.point(rect.right(), rect.bottom());
+        if rect.width > 0 && rect.height > 0 {
+            (x0, y0, x1.max(x0 + 1), y1.max(y0 + 1))
+        } else {
+            (x0, y0, x1, y1)
+        }
+    }
+}
+
+/// Draw a `Map` to an image. The image covers every `Level` and `FillerRect`,
+/// and is empty if there are none. Fails if the image would be wider or taller
+/// than `max_size`.
+pub fn render(map: &Map, options: &RenderOptions) -> Result<'static, Image> {
+    let layers = &options.layers;
+    let levels = &map.levels.levels;
+    let fillers: Vec<Rect> = map
+        .filler
+        .rects
+        .iter()
+        .map(|rect| {
+            let tiles = |tiles: i32| tiles.saturating_mul(8);
+            Rect::new(tiles(rect.x), tiles(rect.y), tiles(rect.w), tiles(rect.h))
+        })
+        .collect();
+
+    let all = levels
+        .iter()
+        .map(Level::bounds)
+        .chain(fillers.iter().copied());
+    let bounds = all.reduce(|a, b| {
+        let (x, y) = (a.x.min(b.x), a.y.min(b.y));
+        let (right, bottom) = (a.right().max(b.right()), a.bottom().max(b.bottom()));
+        Rect::new(x, y, right.saturating_sub(x), bottom.saturating_sub(y))
+    });
+    let bounds = match bounds {
+        Some(bounds) => bounds,
+        None => return Ok(Image::new(0, 0, CLEAR)),
+    };
+
+    let view = View {
+        origin_x: bounds.x,
+        origin_y: bounds.y,
+        scale: options.scale,
+    };
+    let (_, _, width, height) = view.rect(bounds);
+    let size = |pixels: i64| match u32::try_from(pixels.max(0)) {
+        Ok(pixels) if pixels <= options.max_size => Ok(pixels),
+        _ => Err(Error::io(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "A {}x{} image is larger than the maximum size of {}",
+                width, height, options.max_size
+            ),
+        )),
+    };
+    let mut image = Image::new(size(width)?, size(height)?, CLEAR);
+
+    let fill = |image: &mut Image, rect: Rect, color: Color| {
+        let (x0, y0, x1, y1) = view.rect(rect);
+        image.fill_rect(x0, y0, x1, y1, color);
+    };
+
+    if layers.fillers {
+        for rect in &fillers {
+            fill(&mut image, *rect, FILLER);
+        }
+    }
+
+    for level in levels {
+        if layers.rooms {
+            fill(&mut image, level.bounds(), ROOM);
+        }
+        if layers.background {
+            draw_tiles(&mut image, &view, level, &level.bg_grid(), 0.6);
+        }
+        if layers.solids {
+            draw_tiles(&mut image, &view, level, &level.solids_grid(), 1.0);
+        }
+    }
+
+    for level in levels {
+        if layers.triggers {
+            for trigger in &level.triggers.triggers {
+                let (x, y) = match trigger.position() {
+                    Some(position) => level.to_world(position.0, position.1),
+                    None => continue,
+                };
+                let (width, height) = trigger.size().unwrap_or((8, 8));
+                let rect = Rect::new(x, y, width, height);
+                draw_object(&mut image, &view, rect, TRIGGER, TRIGGER_OUTLINE);
+                if layers.labels {
+                    draw_label(&mut image, &view, rect, trigger.name());
+                }
+            }
+        }
+
+        if layers.entities {
+            for entity in &level.entities.entities {
+                let (x, y) = match level.entity_world_position(entity) {
+                    Some(position) => position,
+                    None => continue,
+                };
+                let rect = match entity.size() {
+                    (Some(width), Some(height)) => Rect::new(x, y, width, height),
+                    (Some(width), None) => Rect::new(x, y, width, 8),
+                    (None, Some(height)) => Rect::new(x, y, 8, height),
+                    // Entities without a size are drawn around their origin.
+                    (None, None) => Rect::new(x.saturating_sub(4), y.saturating_sub(4), 8, 8),
+                };
+                draw_object(&mut image, &view, rect, ENTITY, ENTITY_OUTLINE);
+                if layers.labels {
+                    draw_label(&mut image, &view, rect, entity.name());
+                }
+            }
+        }
+    }
+
+    for level in levels {
+        if layers.rooms {
+            let (x0, y0, x1, y1) = view.rect(level.bounds());
+            image.outline_rect(x0, y0, x1, y1, ROOM_OUTLINE);
+        }
+        if layers.labels {
+            draw_label(&mut image, &view, level.bounds(), &level.name);
+        }
+    }
+
+    Ok(image)
+}
+
+fn draw_tiles(
+    image: &mut Image,
+    view: &View,
+    level: &Level,
+    grid: &super::tiles::TileGrid<char>,
+    brightness: f32,
+) {
+    for (y, row) in grid.rows().enumerate() {
+        for (x, tile) in row.iter().enumerate() {
+            if *tile == '0' {
+                continue;
+            }
+            let base = TILESETS[*tile as usize % TILESETS.len()];
+            let mut color = base;
+            for channel in color.iter_mut().take(3) {
+                *channel = (*channel as f32 * brightness) as u8;
+            }
+
+            let (x, y) = level.to_world((x as i32).saturating_mul(8), (y as i32).saturating_mul(8));
+            let (x0, y0, x1, y1) = view.rect(Rect::new(x, y, 8, 8));
+            image.fill_rect(x0, y0, x1, y1, color);
+        }
+    }
+}
+
+fn draw_object(image: &mut Image, view: &View, rect: Rect, fill: Color, outline: Color) {
+    let (x0, y0, x1, y1) = view.rect(rect);
+    image.fill_rect(x0, y0, x1, y1, fill);
+    image.outline_rect(x0, y0, x1, y1, outline);
+}
+
+fn draw_label(image: &mut Image, view: &View, rect: Rect, text: &str) {
+    let (x0, y0, x1, y1) = view.rect(rect);
+    // Labels only go in rectangles with room for at least one letter.
+    if x1 - x0 >= 5 && y1 - y0 >= 7 {
+        image.draw_text(x0 + 1, y0 + 1, x1 - 1, text, LABEL);
+    }
+}
+
+/// A 3x5 glyph, one row per byte with the leftmost pixel in the third bit.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        ' ' => [0b000; 5],
+        _ => [0b110, 0b001, 0b010, 0b000, 0b010],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::maps::entities::{Entity, Player};
+    use crate::maps::FillerRect;
+
+    fn map() -> Map {
+        let mut level = Level {
+            name: "a".to_string(),
+            x: 0,
+            y: 0,
+            width: 64,
+            height: 32,
+            ..Level::default()
+        };
+        level.solids.contents = "11\n1".to_string();
+        level.entities.entities.push(Entity::Player(Player {
+            id: 0,
+            x: 32,
+            y: 24,
+            ..Player::default()
+        }));
+
+        let mut map = Map::default();
+        map.levels.levels.push(level);
+        map.filler.rects.push(FillerRect {
+            x: -2,
+            y: 0,
+            w: 2,
+            h: 1,
+        });
+        map
+    }
+
+    #[test]
+    fn draws_layers() {
+        let image = render(&map(), &RenderOptions::default()).unwrap();
+        assert_eq!((image.width(), image.height()), (80, 32));
+
+        // The filler is left of the room, so the room starts at x = 16.
+        assert_eq!(image.get(0, 0), Some(FILLER));
+        assert_eq!(image.get(0, 20), Some(CLEAR));
+        assert_eq!(image.get(16, 0), Some(ROOM_OUTLINE));
+        assert_eq!(image.get(16 + 12, 4), Some(TILESETS[b'1' as usize % 8]));
+        assert_eq!(image.get(16 + 20, 20), Some(ROOM));
+        assert_eq!(image.get(16 + 28, 20), Some(ENTITY_OUTLINE));
+        assert_ne!(image.get(16 + 30, 22), Some(ROOM));
+
+        let no_tiles = RenderOptions {
+            layers: Layers {
+                solids: false,
+                entities: false,
+                ..Layers::default()
+            },
+            ..RenderOptions::default()
+        };
+        let image = render(&map(), &no_tiles).unwrap();
+        assert_eq!(image.get(16 + 12, 4), Some(ROOM));
+        assert_eq!(image.get(16 + 30, 22), Some(ROOM));
+    }
+
+    #[test]
+    fn scales() {
+        let options = RenderOptions {
+            scale: 0.125,
+            ..RenderOptions::default()
+        };
+        let image = render(&map(), &options).unwrap();
+        assert_eq!((image.width(), image.height()), (10, 4));
+        assert_eq!(
+            render(&Map::default(), &options).unwrap().pixels(),
+            &[] as &[u8]
+        );
+    }
+
+    #[test]
+    fn limits_size() {
+        let mut wide = map();
+        wide.levels.levels[0].x = i32::MAX - 8;
+        wide.filler.rects[0].x = i32::MIN / 8;
+        let err = render(&wide, &RenderOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("larger than the maximum size"));
+
+        let options = RenderOptions {
+            max_size: 79,
+            ..RenderOptions::default()
+        };
+        assert!(render(&map(), &options).is_err());
+        let options = RenderOptions {
+            scale: 0.125,
+            ..options
+        };
+        assert!(render(&map(), &options).is_ok());
+    }
+
+    #[test]
+    fn writes_png() {
+        let mut png = Vec::new();
+        render(&map(), &RenderOptions::default())
+            .unwrap()
+            .write_png(&mut png)
+            .unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+    }
+}
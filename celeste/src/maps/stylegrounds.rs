@@ -0,0 +1,619 @@
+//! Typed stylegrounds, or the animated layers drawn behind and in front of
+//! `Level`s. Layers are either `parallax` images, effects such as `snowFg`, or
+//! `apply` groups that hand their attributes down to their children.
+//!
+//! Attributes that aren't known, or that have an unexpected type, are kept
+//! as-is, and all attributes are written back in the order they were read.
+
+use super::attrs::*;
+use crate::binel::serialize::*;
+use crate::binel::*;
+use crate::Result;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::prelude::v1::*;
+
+/// Which `Level`s and session flags a layer is shown for. Shared by
+/// `Parallax` and `Effect`.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Visibility {
+    /// Comma-separated `Level` names, where `*` is a wildcard.
+    pub only: Option<String>,
+    /// Comma-separated `Level` names, where `*` is a wildcard.
+    pub exclude: Option<String>,
+    /// Only shown while this session flag is set.
+    pub flag: Option<String>,
+    /// Only shown while this session flag is unset.
+    pub notflag: Option<String>,
+    /// Used by cutscenes to find the layer.
+    pub tag: Option<String>,
+}
+
+impl Visibility {
+    fn take(attrs: &mut Attributes) -> Self {
+        Visibility {
+            only: take(attrs, "only"),
+            exclude: take(attrs, "exclude"),
+            flag: take(attrs, "flag"),
+            notflag: take(attrs, "notflag"),
+            tag: take(attrs, "tag"),
+        }
+    }
+
+    fn put(self, attrs: &mut Attributes) {
+        put(attrs, "only", self.only);
+        put(attrs, "exclude", self.exclude);
+        put(attrs, "flag", self.flag);
+        put(attrs, "notflag", self.notflag);
+        put(attrs, "tag", self.tag);
+    }
+}
+
+/// How a `Parallax` is blended with the layers behind it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BlendMode {
+    /// Stored as `alphablend`. The default.
+    AlphaBlend,
+    /// Stored as `additive`.
+    Additive,
+}
+
+impl BinElType for BlendMode {
+    fn into_binel(self) -> BinElValue {
+        let mode = match self {
+            BlendMode::AlphaBlend => "alphablend",
+            BlendMode::Additive => "additive",
+        };
+        BinElValue::Attribute(mode.into())
+    }
+
+    fn from_binel(binel: BinElValue) -> Result<'static, Self> {
+        match binel {
+            BinElValue::Attribute(BinElAttr::Text(mode)) if mode == "alphablend" => {
+                Ok(BlendMode::AlphaBlend)
+            }
+            BinElValue::Attribute(BinElAttr::Text(mode)) if mode == "additive" => {
+                Ok(BlendMode::Additive)
+            }
+            other => Err(crate::Error::deserialize("blend mode", other.describe())),
+        }
+    }
+
+    fn maybe_elem() -> bool {
+        false
+    }
+}
+
+/// A scrolling image. Every field is optional, as it may be handed down by an
+/// `Apply`.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Parallax {
+    /// The texture, within the Gameplay atlas.
+    pub texture: Option<String>,
+    pub x: Option<Number>,
+    pub y: Option<Number>,
+    /// How far the layer moves relative to the camera. 0 is fixed to the
+    /// screen, 1 moves with the `Level`.
+    pub scroll_x: Option<Number>,
+    pub scroll_y: Option<Number>,
+    /// Constant movement, in pixels per second.
+    pub speed_x: Option<Number>,
+    pub speed_y: Option<Number>,
+    pub alpha: Option<Number>,
+    pub blend_mode: Option<BlendMode>,
+    /// Whether the texture repeats horizontally.
+    pub loop_x: Option<bool>,
+    /// Whether the texture repeats vertically.
+    pub loop_y: Option<bool>,
+    pub flip_x: Option<bool>,
+    pub flip_y: Option<bool>,
+    pub visibility: Visibility,
+    /// All other attributes, such as `color` or `fadex`.
+    pub other: Attributes,
+    /// The attribute names in the order they were read. Attributes that
+    /// aren't listed are written after the rest.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub order: Vec<String>,
+}
+
+impl Parallax {
+    fn from_attrs(mut attrs: Attributes) -> Self {
+        Parallax {
+            order: order(&attrs),
+            texture: take(&mut attrs, "texture"),
+            x: take(&mut attrs, "x"),
+            y: take(&mut attrs, "y"),
+            scroll_x: take(&mut attrs, "scrollx"),
+            scroll_y: take(&mut attrs, "scrolly"),
+            speed_x: take(&mut attrs, "speedx"),
+            speed_y: take(&mut attrs, "speedy"),
+            alpha: take(&mut attrs, "alpha"),
+            blend_mode: take(&mut attrs, "blendmode"),
+            loop_x: take(&mut attrs, "loopx"),
+            loop_y: take(&mut attrs, "loopy"),
+            flip_x: take(&mut attrs, "flipx"),
+            flip_y: take(&mut attrs, "flipy"),
+            visibility: Visibility::take(&mut attrs),
+            other: attrs,
+        }
+    }
+
+    fn into_attrs(self) -> Attributes {
+        let mut attrs = Attributes::new();
+        put(&mut attrs, "texture", self.texture);
+        put(&mut attrs, "x", self.x);
+        put(&mut attrs, "y", self.y);
+        put(&mut attrs, "scrollx", self.scroll_x);
+        put(&mut attrs, "scrolly", self.scroll_y);
+        put(&mut attrs, "speedx", self.speed_x);
+        put(&mut attrs, "speedy", self.speed_y);
+        put(&mut attrs, "alpha", self.alpha);
+        put(&mut attrs, "blendmode", self.blend_mode);
+        put(&mut attrs, "loopx", self.loop_x);
+        put(&mut attrs, "loopy", self.loop_y);
+        put(&mut attrs, "flipx", self.flip_x);
+        put(&mut attrs, "flipy", self.flip_y);
+        self.visibility.put(&mut attrs);
+        attrs.extend(self.other);
+        reorder_attributes(&mut attrs, &self.order);
+        attrs
+    }
+}
+
+/// The kind of an `Effect`. Celeste matches effect names case-insensitively.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(missing_docs)]
+pub enum EffectKind {
+    Stars,
+    SnowFg,
+    SnowBg,
+    Rain,
+    Godrays,
+    Planets,
+    HeatWave,
+    Petals,
+    Wind,
+    NorthernLights,
+    StarField,
+    DreamStars,
+    BlackHole,
+    /// An effect not known by this crate, such as one added by a mod.
+    Unknown,
+}
+
+/// The `stars` effect, which has no fields of its own.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Stars {}
+
+/// The `snowFg` effect, which has no fields of its own.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SnowFg {}
+
+/// The `rain` effect.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rain {
+    /// The hex color of the drops, `161933` by default. Everest extension.
+    pub color: Option<String>,
+}
+
+/// The `godrays` effect, which has no fields of its own.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Godrays {}
+
+/// The `planets` effect.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Planets {
+    /// How many planets are drawn, 32 by default. Celeste reads it as a float
+    /// and truncates it.
+    pub count: Option<Number>,
+    /// Either `small`, the default, or `big`.
+    pub size: Option<String>,
+}
+
+/// The fields of an `Effect` that are specific to its kind.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[allow(missing_docs)]
+pub enum EffectFields {
+    Stars(Stars),
+    SnowFg(SnowFg),
+    Rain(Rain),
+    Godrays(Godrays),
+    Planets(Planets),
+    /// An effect without typed fields, whose attributes are all kept in
+    /// `Effect::other`.
+    #[default]
+    Other,
+}
+
+impl EffectFields {
+    fn take(kind: EffectKind, attrs: &mut Attributes) -> Self {
+        match kind {
+            EffectKind::Stars => EffectFields::Stars(Stars {}),
+            EffectKind::SnowFg => EffectFields::SnowFg(SnowFg {}),
+            EffectKind::Rain => EffectFields::Rain(Rain {
+                color: take(attrs, "color"),
+            }),
+            EffectKind::Godrays => EffectFields::Godrays(Godrays {}),
+            EffectKind::Planets => EffectFields::Planets(Planets {
+                count: take(attrs, "count"),
+                size: take(attrs, "size"),
+            }),
+            _ => EffectFields::Other,
+        }
+    }
+
+    fn put(self, attrs: &mut Attributes) {
+        match self {
+            EffectFields::Rain(rain) => put(attrs, "color", rain.color),
+            EffectFields::Planets(planets) => {
+                put(attrs, "count", planets.count);
+                put(attrs, "size", planets.size);
+            }
+            EffectFields::Stars(_)
+            | EffectFields::SnowFg(_)
+            | EffectFields::Godrays(_)
+            | EffectFields::Other => {}
+        }
+    }
+}
+
+/// An animated layer drawn by code, such as falling snow.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Effect {
+    /// The element name, such as `snowFg`.
+    pub name: String,
+    /// The fields specific to the kind of effect.
+    pub fields: EffectFields,
+    pub visibility: Visibility,
+    /// All other attributes, such as `fadex`.
+    pub other: Attributes,
+    /// The attribute names in the order they were read. Attributes that
+    /// aren't listed are written after the rest.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub order: Vec<String>,
+}
+
+impl Effect {
+    /// The kind of effect, based on its name.
+    ///
+    /// # Examples:
+    /// ```
+    /// use celeste::maps::stylegrounds::*;
+    ///
+    /// let effect = Effect {
+    ///     name: "snowfg".to_string(),
+    ///     ..Effect::default()
+    /// };
+    /// assert_eq!(effect.kind(), EffectKind::SnowFg);
+    /// ```
+    pub fn kind(&self) -> EffectKind {
+        match self.name.to_lowercase().as_str() {
+            "stars" => EffectKind::Stars,
+            "snowfg" => EffectKind::SnowFg,
+            "snowbg" => EffectKind::SnowBg,
+            "rain" => EffectKind::Rain,
+            "godrays" => EffectKind::Godrays,
+            "planets" => EffectKind::Planets,
+            "heatwave" => EffectKind::HeatWave,
+            "petals" => EffectKind::Petals,
+            "wind" => EffectKind::Wind,
+            "northernlights" => EffectKind::NorthernLights,
+            "starfield" => EffectKind::StarField,
+            "dreamstars" => EffectKind::DreamStars,
+            "blackhole" => EffectKind::BlackHole,
+            _ => EffectKind::Unknown,
+        }
+    }
+}
+
+/// A group of layers that all receive its attributes, unless they set them
+/// themselves.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Apply {
+    /// The attributes handed down to the children.
+    pub attributes: Attributes,
+    pub children: Vec<Styleground>,
+}
+
+impl Apply {
+    /// The children with this group's attributes handed down, as Celeste does.
+    /// Attributes set on a child take precedence, and nested groups are
+    /// flattened.
+    ///
+    /// # Examples:
+    /// ```
+    /// use celeste::binel::*;
+    /// use celeste::maps::stylegrounds::*;
+    ///
+    /// let apply = BinEl::build("apply")
+    ///     .attr("scrollx", 0.5)
+    ///     .attr("loopx", true)
+    ///     .child(BinEl::build("parallax").attr("texture", "bgs/04/bg0"))
+    ///     .child(BinEl::build("parallax").attr("texture", "bgs/04/bg1").attr("scrollx", 1))
+    ///     .finish();
+    ///
+    /// let apply = match Styleground::from_elem(apply) {
+    ///     Styleground::Apply(apply) => apply,
+    ///     other => panic!("Expected apply, got {:?}", other),
+    /// };
+    ///
+    /// let scroll: Vec<_> = apply
+    ///     .resolve()
+    ///     .into_iter()
+    ///     .map(|layer| match layer {
+    ///         Styleground::Parallax(parallax) => {
+    ///             (parallax.scroll_x.map(|scroll| scroll.get()), parallax.loop_x)
+    ///         }
+    ///         other => panic!("Expected parallax, got {:?}", other),
+    ///     })
+    ///     .collect();
+    /// assert_eq!(scroll, vec![(Some(0.5), Some(true)), (Some(1.0), Some(true))]);
+    /// ```
+    pub fn resolve(&self) -> Vec<Styleground> {
+        let mut layers = Vec::new();
+        for child in &self.children {
+            let mut elem = child.clone().into_elem();
+            for (key, val) in &self.attributes {
+                if !elem.attributes.contains_key(key) {
+                    elem.attributes.insert(key.clone(), val.clone());
+                }
+            }
+
+            match Styleground::from_elem(elem) {
+                Styleground::Apply(apply) => layers.extend(apply.resolve()),
+                layer => layers.push(layer),
+            }
+        }
+        layers
+    }
+}
+
+/// A single layer of a `Foregrounds` or `Backgrounds`.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Styleground {
+    /// Stored as `parallax`.
+    Parallax(Parallax),
+    /// Stored as `apply`.
+    Apply(Apply),
+    /// Stored under the effect's name.
+    Effect(Effect),
+    /// A `parallax` or effect with children, which Celeste doesn't use.
+    Other(BinEl),
+}
+
+impl Styleground {
+    /// Parse a layer from a `BinEl`.
+    pub fn from_elem(mut elem: BinEl) -> Self {
+        match elem.name.as_str() {
+            "apply" => Styleground::Apply(Apply {
+                children: elem.drain().map(Styleground::from_elem).collect(),
+                attributes: elem.attributes,
+            }),
+            _ if elem.children().next().is_some() => Styleground::Other(elem),
+            "parallax" => Styleground::Parallax(Parallax::from_attrs(elem.attributes)),
+            _ => {
+                let mut effect = Effect {
+                    name: elem.name,
+                    order: order(&elem.attributes),
+                    ..Effect::default()
+                };
+                let mut attrs = elem.attributes;
+                effect.fields = EffectFields::take(effect.kind(), &mut attrs);
+                effect.visibility = Visibility::take(&mut attrs);
+                effect.other = attrs;
+                Styleground::Effect(effect)
+            }
+        }
+    }
+
+    /// Convert the layer back into a `BinEl`.
+    pub fn into_elem(self) -> BinEl {
+        match self {
+            Styleground::Parallax(parallax) => {
+                let mut elem = BinEl::new("parallax");
+                elem.attributes = parallax.into_attrs();
+                elem
+            }
+            Styleground::Apply(apply) => {
+                let mut elem = BinEl::new("apply");
+                elem.attributes = apply.attributes;
+                for child in apply.children {
+                    elem.insert(child.into_elem());
+                }
+                elem
+            }
+            Styleground::Effect(effect) => {
+                let mut elem = BinEl::new(&effect.name);
+                effect.fields.put(&mut elem.attributes);
+                effect.visibility.put(&mut elem.attributes);
+                elem.attributes.extend(effect.other);
+                reorder_attributes(&mut elem.attributes, &effect.order);
+                elem
+            }
+            Styleground::Other(elem) => elem,
+        }
+    }
+}
+
+impl BinElType for Styleground {
+    fn into_binel(self) -> BinElValue {
+        BinElValue::Element(self.into_elem())
+    }
+
+    fn from_binel(binel: BinElValue) -> Result<'static, Self> {
+        BinEl::from_binel(binel).map(Styleground::from_elem)
+    }
+
+    fn maybe_attr() -> bool {
+        false
+    }
+}
+
+/// Flatten all `Apply` groups in a list of layers, handing their attributes
+/// down to their children.
+pub fn resolve(layers: &[Styleground]) -> Vec<Styleground> {
+    let group = Apply {
+        attributes: Attributes::new(),
+        children: layers.to_vec(),
+    };
+    group.resolve()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn backgrounds() -> BinEl {
+        BinEl::build("Backgrounds")
+            .child(
+                BinEl::build("parallax")
+                    .attr("texture", "bgs/07/07/bg0")
+                    .attr("x", 0)
+                    .attr("scrollx", 0.25)
+                    .attr("blendmode", "additive")
+                    .attr("loopx", true)
+                    .attr("only", "a-*")
+                    .attr("color", "ffffff"),
+            )
+            .child(
+                BinEl::build("apply")
+                    .attr("exclude", "b-00")
+                    .attr("alpha", 0.5)
+                    .child(BinEl::build("snowFg"))
+                    .child(BinEl::build("parallax").attr("blendmode", "multiply")),
+            )
+            .child(
+                BinEl::build("planets")
+                    .attr("count", 32)
+                    .attr("size", "big"),
+            )
+            .finish()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let bg = crate::maps::Backgrounds::from_binel(BinElValue::Element(backgrounds())).unwrap();
+        assert_eq!(bg.layers.len(), 3);
+
+        match &bg.layers[0] {
+            Styleground::Parallax(parallax) => {
+                assert_eq!(parallax.x, Some(Number::Int(0)));
+                assert_eq!(parallax.scroll_x, Some(Number::Float(0.25)));
+                assert_eq!(parallax.blend_mode, Some(BlendMode::Additive));
+                assert_eq!(parallax.visibility.only.as_deref(), Some("a-*"));
+                assert_eq!(parallax.other.len(), 1);
+            }
+            other => panic!("Expected parallax, got {:?}", other),
+        }
+        match &bg.layers[2] {
+            Styleground::Effect(effect) => {
+                assert_eq!(effect.kind(), EffectKind::Planets);
+                assert_eq!(
+                    effect.fields,
+                    EffectFields::Planets(Planets {
+                        count: Some(Number::Int(32)),
+                        size: Some("big".to_string()),
+                    })
+                );
+                assert!(effect.other.is_empty());
+            }
+            other => panic!("Expected effect, got {:?}", other),
+        }
+
+        match bg.into_binel() {
+            BinElValue::Element(elem) => assert_eq!(elem, backgrounds()),
+            _ => panic!("Didn't get element!"),
+        }
+    }
+
+    #[test]
+    fn keep_order_and_floats() {
+        let layers = vec![
+            BinEl::build("parallax")
+                .attr("only", "a-*")
+                .attr("alpha", 1.0)
+                .attr("texture", "bgs/07/07/bg0")
+                .attr("fadex", "0:1")
+                .attr("x", 0)
+                .finish(),
+            BinEl::build("rain")
+                .attr("scrolly", 1.0)
+                .attr("exclude", "b-00")
+                .attr("color", "ffffff")
+                .finish(),
+            BinEl::build("apply")
+                .attr("loopy", true)
+                .attr("alpha", 1.0)
+                .child(
+                    BinEl::build("godrays")
+                        .attr("fadey", "0:1")
+                        .attr("tag", "rays"),
+                )
+                .finish(),
+        ];
+
+        match Styleground::from_elem(layers[0].clone()) {
+            Styleground::Parallax(parallax) => {
+                assert_eq!(parallax.alpha, Some(Number::Float(1.0)));
+                assert_eq!(parallax.x, Some(Number::Int(0)));
+            }
+            other => panic!("Expected parallax, got {:?}", other),
+        }
+
+        // equality ignores the order of attributes
+        fn keys(elem: &BinEl) -> Vec<Vec<&String>> {
+            let mut all = vec![elem.attributes.keys().collect()];
+            all.extend(elem.children().flat_map(keys));
+            all
+        }
+        for layer in &layers {
+            let elem = Styleground::from_elem(layer.clone()).into_elem();
+            assert_eq!(&elem, layer);
+            assert_eq!(keys(&elem), keys(layer));
+        }
+    }
+
+    #[test]
+    fn resolve_apply() {
+        let layers: Vec<_> = backgrounds().drain().map(Styleground::from_elem).collect();
+        let resolved = resolve(&layers);
+        assert_eq!(resolved.len(), 4);
+
+        match &resolved[1] {
+            Styleground::Effect(effect) => {
+                assert_eq!(effect.visibility.exclude.as_deref(), Some("b-00"));
+                assert_eq!(effect.other.get("alpha"), Some(&BinElAttr::Float(0.5)));
+            }
+            other => panic!("Expected effect, got {:?}", other),
+        }
+        match &resolved[2] {
+            Styleground::Parallax(parallax) => {
+                assert_eq!(parallax.alpha, Some(Number::Float(0.5)));
+                assert_eq!(parallax.blend_mode, None);
+                assert_eq!(
+                    parallax.other.get("blendmode"),
+                    Some(&BinElAttr::Text("multiply".to_string()))
+                );
+            }
+            other => panic!("Expected parallax, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Tile grids, parsed from the text of `Solids`, `BGSolids` and `ObjTiles`.
+
+use crate::{Error, Result};
+use std::fmt;
+use std::prelude::v1::*;
+
+/// A single tile in a `TileGrid`.
+pub trait Tile: Copy + PartialEq {
+    /// The tile used for empty space, which is trimmed when serializing.
+    const EMPTY: Self;
+
+    /// Parse a row of tiles.
+    fn parse_row(row: &str) -> Result<'static, Vec<Self>>;
+
+    /// Write a row of tiles, which has already been trimmed.
+    fn write_row(row: &[Self], f: &mut fmt::Formatter) -> fmt::Result;
+}
+
+/// Tilesets in `Solids` and `BGSolids`, one character per tile, where `0` is
+/// air.
+impl Tile for char {
+    const EMPTY: Self = '0';
+
+    fn parse_row(row: &str) -> Result<'static, Vec<Self>> {
+        Ok(row.chars().collect())
+    }
+
+    fn write_row(row: &[Self], f: &mut fmt::Formatter) -> fmt::Result {
+        row.iter().try_for_each(|tile| write!(f, "{}", tile))
+    }
+}
+
+/// Tile indices in `ObjTiles`, separated by commas, where `-1` is empty.
+impl Tile for i32 {
+    const EMPTY: Self = -1;
+
+    fn parse_row(row: &str) -> Result<'static, Vec<Self>> {
+        if row.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        row.split(',')
+            .map(|tile| {
+                tile.trim()
+                    .parse()
+                    .map_err(|_| Error::deserialize("a tile index", format!("`{}`", tile)))
+            })
+            .collect()
+    }
+
+    fn write_row(row: &[Self], f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, tile) in row.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", tile)?;
+        }
+        Ok(())
+    }
+}
+
+/// A fixed-size 2D grid of tiles, indexed by tile coordinates from the top
+/// left of a `Level`. Displaying it gives the format the game expects, with
+/// empty tiles trimmed from the end of each row and empty rows trimmed from the
+/// end.
+///
+/// # Examples:
+/// ```
+/// use celeste::maps::tiles::*;
+///
+/// let mut grid = TileGrid::<char>::parse("11\n1", 4, 3).unwrap();
+/// assert_eq!(grid.get(1, 0), Some('1'));
+/// assert_eq!(grid.get(1, 1), Some('0'));
+///
+/// grid.fill_rect(2, 2, 2, 1, '3');
+/// assert_eq!(grid.flood_fill(0, 0, '2'), 3);
+/// assert_eq!(grid.to_string(), "22\n2\n0033");
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TileGrid<T> {
+    width: usize,
+    height: usize,
+    tiles: Vec<T>,
+}
+
+impl<T: Tile> TileGrid<T> {
+    /// Create a grid filled with empty tiles.
+    pub fn new(width: usize, height: usize) -> Self {
+        TileGrid {
+            width,
+            height,
+            tiles: vec![T::EMPTY; width * height],
+        }
+    }
+
+    /// Parse a grid from newline-separated rows. Missing tiles are empty, and
+    /// tiles outside of the grid are ignored.
+    pub fn parse(text: &str, width: usize, height: usize) -> Result<'static, Self> {
+        let mut grid = Self::new(width, height);
+        for (y, row) in text.lines().take(height).enumerate() {
+            for (x, tile) in T::parse_row(row)?.into_iter().take(width).enumerate() {
+                grid.tiles[y * width + x] = tile;
+            }
+        }
+        Ok(grid)
+    }
+
+    /// The width in tiles.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The height in tiles.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y * self.width + x)
+        } else {
+            None
+        }
+    }
+
+    /// Get a tile, if it is inside the grid.
+    pub fn get(&self, x: usize, y: usize) -> Option<T> {
+        self.index(x, y).map(|i| self.tiles[i])
+    }
+
+    /// Set a tile, returning the previous one. Does nothing if it is outside of
+    /// the grid.
+    pub fn set(&mut self, x: usize, y: usize, tile: T) -> Option<T> {
+        let i = self.index(x, y)?;
+        Some(std::mem::replace(&mut self.tiles[i], tile))
+    }
+
+    /// The rows of the grid, from top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> + '_ {
+        self.tiles.chunks(self.width.max(1)).take(self.height)
+    }
+
+    /// Resize the grid, keeping tiles at the same coordinates. New tiles are
+    /// empty.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let mut resized = Self::new(width, height);
+        for y in 0..self.height.min(height) {
+            for x in 0..self.width.min(width) {
+                resized.tiles[y * width + x] = self.tiles[y * self.width + x];
+            }
+        }
+        *self = resized;
+    }
+
+    /// Fill a rectangle, clipped to the grid.
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, tile: T) {
+        let right = x.saturating_add(width).min(self.width);
+        let bottom = y.saturating_add(height).min(self.height);
+        for y in y..bottom {
+            for x in x..right {
+                self.tiles[y * self.width + x] = tile;
+            }
+        }
+    }
+
+    /// Replace the tile at a position and all orthogonally connected tiles of
+    /// the same kind. Returns how many tiles were replaced.
+    pub fn flood_fill(&mut self, x: usize, y: usize, tile: T) -> usize {
+        let target = match self.get(x, y) {
+            Some(target) if target != tile => target,
+            _ => return 0,
+        };
+
+        let mut filled = 0;
+        let mut stack = vec![(x, y)];
+        while let Some((x, y)) = stack.pop() {
+            match self.index(x, y) {
+                Some(i) if self.tiles[i] == target => {
+                    self.tiles[i] = tile;
+                    filled += 1;
+                }
+                _ => continue,
+            }
+
+            stack.push((x + 1, y));
+            stack.push((x, y + 1));
+            if x > 0 {
+                stack.push((x - 1, y));
+            }
+            if y > 0 {
+                stack.push((x, y - 1));
+            }
+        }
+        filled
+    }
+}
+
+impl<T: Tile> fmt::Display for TileGrid<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let trimmed: Vec<&[T]> = self
+            .rows()
+            .map(|row| {
+                let len = row
+                    .iter()
+                    .rposition(|tile| *tile != T::EMPTY)
+                    .map_or(0, |i| i + 1);
+                &row[..len]
+            })
+            .collect();
+        let len = trimmed
+            .iter()
+            .rposition(|row| !row.is_empty())
+            .map_or(0, |i| i + 1);
+
+        for (i, row) in trimmed[..len].iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            T::write_row(row, f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn objtiles_roundtrip() {
+        let grid = TileGrid::<i32>::parse("-1,-1,12\n\n4,-1,-1,-1\n-1", 5, 4).unwrap();
+        assert_eq!(grid.get(2, 0), Some(12));
+        assert_eq!(grid.get(0, 2), Some(4));
+        assert_eq!(grid.to_string(), "-1,-1,12\n\n4");
+
+        assert!(TileGrid::<i32>::parse("1,a", 2, 1).is_err());
+    }
+
+    #[test]
+    fn edit_grid() {
+        let mut grid = TileGrid::<char>::parse("1111\n1001\n1111\n0001", 4, 4).unwrap();
+        assert_eq!(grid.flood_fill(0, 0, '3'), 11);
+        assert_eq!(grid.flood_fill(0, 0, '3'), 0);
+        assert_eq!(grid.to_string(), "3333\n3003\n3333\n0003");
+
+        grid.fill_rect(1, 1, 10, 10, '0');
+        grid.resize(2, 5);
+        assert_eq!(grid.to_string(), "33\n3\n3");
+        assert_eq!(grid.get(1, 4), Some('0'));
+        assert_eq!(grid.set(2, 0, '1'), None);
+    }
+}
@@ -0,0 +1,348 @@
+//! Typed definitions for the vanilla triggers. Triggers that aren't known, or
+//! that can't be represented by their typed definition without losing data,
+//! are kept as a raw `BinEl` in `Trigger::Other`.
+
+use super::attrs::{match_typed, parse_lossless, typed_elem, typed_object};
+use super::entities::Node;
+use crate::binel::serialize::*;
+use crate::binel::*;
+use crate::Result;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::prelude::v1::*;
+
+// Every trigger also has a size.
+macro_rules! trigger {
+    ($(#[$($meta:tt)*])* $ident:ident = $name:tt { $($fields:tt)* }) => {
+        typed_object! {
+            $(#[$($meta)*])*
+            $ident = $name [
+                /// The width in pixels.
+                width: i32,
+                /// The height in pixels.
+                height: i32,
+            ] {
+                $($fields)*
+            }
+        }
+    };
+}
+
+trigger! {
+    /// Moves the camera while the player is inside.
+    CameraOffsetTrigger = "cameraOffsetTrigger" {
+        /// The horizontal offset, in units of 48 pixels.
+        #[celeste_name = "cameraX"]
+        camera_x: Number,
+        /// The vertical offset, in units of 32 pixels.
+        #[celeste_name = "cameraY"]
+        camera_y: Number,
+    }
+}
+
+trigger! {
+    /// Changes the music when entered.
+    MusicTrigger = "musicTrigger" {
+        /// The FMOD event, such as `event:/music/lvl1/main`.
+        track: String,
+        reset_on_leave: bool,
+        /// The music progress parameter. Everest extension.
+        progress: Option<i32>,
+    }
+}
+
+trigger! {
+    /// Fades music parameters depending on the player's position inside.
+    MusicFadeTrigger = "musicFadeTrigger" {
+        /// Fade horizontally if set, otherwise vertically.
+        left_to_right: bool,
+        /// The parameter value at the left or top edge.
+        fade_a: Number,
+        /// The parameter value at the right or bottom edge.
+        fade_b: Number,
+        /// The FMOD parameter. Everest extension.
+        parameter: Option<String>,
+    }
+}
+
+trigger! {
+    /// Changes the wind pattern when entered.
+    WindTrigger = "windTrigger" {
+        /// The pattern, such as `Left` or `None`.
+        pattern: String,
+    }
+}
+
+trigger! {
+    /// Moves the respawn point to its node, or to the nearest spawn point if
+    /// it has none.
+    ChangeRespawnTrigger = "changeRespawnTrigger" {}
+}
+
+trigger! {
+    /// Starts a scripted event, such as a cutscene.
+    EventTrigger = "eventTrigger" {
+        /// The event, such as `end_city`.
+        event: String,
+    }
+}
+
+trigger! {
+    /// Fades the light level depending on the player's position inside.
+    LightFadeTrigger = "lightFadeTrigger" {
+        light_add_from: Number,
+        light_add_to: Number,
+        /// How the player's position maps to the fade, such as `LeftToRight`.
+        position_mode: String,
+    }
+}
+
+trigger! {
+    /// Fades bloom depending on the player's position inside.
+    BloomFadeTrigger = "bloomFadeTrigger" {
+        bloom_add_from: Number,
+        bloom_add_to: Number,
+        /// How the player's position maps to the fade, such as `LeftToRight`.
+        position_mode: String,
+    }
+}
+
+trigger! {
+    /// Stops the player's dashes from refilling while inside.
+    NoRefillTrigger = "noRefillTrigger" {
+        /// Whether refilling is disabled.
+        state: bool,
+    }
+}
+
+/// A trigger in a `Level`.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[allow(missing_docs)]
+pub enum Trigger {
+    CameraOffset(CameraOffsetTrigger),
+    Music(MusicTrigger),
+    MusicFade(MusicFadeTrigger),
+    Wind(WindTrigger),
+    ChangeRespawn(ChangeRespawnTrigger),
+    Event(EventTrigger),
+    LightFade(LightFadeTrigger),
+    BloomFade(BloomFadeTrigger),
+    NoRefill(NoRefillTrigger),
+    /// An unknown trigger, or one that couldn't be fully represented by its
+    /// typed definition.
+    Other(BinEl),
+}
+
+macro_rules! each_typed {
+    ($trigger:expr, $($arms:tt)*) => {
+        match_typed!(
+            $trigger,
+            Trigger {
+                CameraOffset,
+                Music,
+                MusicFade,
+                Wind,
+                ChangeRespawn,
+                Event,
+                LightFade,
+                BloomFade,
+                NoRefill
+            },
+            $($arms)*
+        )
+    };
+}
+
+impl Trigger {
+    /// Parse a trigger from a `BinEl`, falling back to `Trigger::Other` if it
+    /// is unknown or any of its data would be lost.
+    ///
+    /// # Examples:
+    /// ```
+    /// use celeste::binel::*;
+    /// use celeste::maps::triggers::*;
+    ///
+    /// let wind = BinEl::build("windTrigger")
+    ///     .attr("id", 1)
+    ///     .attr("x", 0)
+    ///     .attr("y", 0)
+    ///     .attr("width", 16)
+    ///     .attr("height", 16)
+    ///     .attr("pattern", "Left")
+    ///     .finish();
+    ///
+    /// match Trigger::from_elem(&wind) {
+    ///     Trigger::Wind(wind) => assert_eq!(wind.pattern, "Left"),
+    ///     other => panic!("Expected wind trigger, got {:?}", other),
+    /// }
+    /// ```
+    pub fn from_elem(elem: &BinEl) -> Self {
+        let name = elem.name.as_str();
+        let typed = match name {
+            "cameraOffsetTrigger" => parse_lossless(elem, name).map(Trigger::CameraOffset),
+            "musicTrigger" => parse_lossless(elem, name).map(Trigger::Music),
+            "musicFadeTrigger" => parse_lossless(elem, name).map(Trigger::MusicFade),
+            "windTrigger" => parse_lossless(elem, name).map(Trigger::Wind),
+            "changeRespawnTrigger" => parse_lossless(elem, name).map(Trigger::ChangeRespawn),
+            "eventTrigger" => parse_lossless(elem, name).map(Trigger::Event),
+            "lightFadeTrigger" => parse_lossless(elem, name).map(Trigger::LightFade),
+            "bloomFadeTrigger" => parse_lossless(elem, name).map(Trigger::BloomFade),
+            "noRefillTrigger" => parse_lossless(elem, name).map(Trigger::NoRefill),
+            _ => None,
+        };
+
+        typed.unwrap_or_else(|| Trigger::Other(elem.clone()))
+    }
+
+    /// Convert the trigger back into a `BinEl`.
+    pub fn into_elem(self) -> BinEl {
+        let name = self.name().to_string();
+        each_typed!(self, typed => typed_elem(typed, &name), elem => elem)
+    }
+
+    /// The element name of the trigger, such as `windTrigger`.
+    pub fn name(&self) -> &str {
+        match self {
+            Trigger::CameraOffset(_) => "cameraOffsetTrigger",
+            Trigger::Music(_) => "musicTrigger",
+            Trigger::MusicFade(_) => "musicFadeTrigger",
+            Trigger::Wind(_) => "windTrigger",
+            Trigger::ChangeRespawn(_) => "changeRespawnTrigger",
+            Trigger::Event(_) => "eventTrigger",
+            Trigger::LightFade(_) => "lightFadeTrigger",
+            Trigger::BloomFade(_) => "bloomFadeTrigger",
+            Trigger::NoRefill(_) => "noRefillTrigger",
+            Trigger::Other(elem) => &elem.name,
+        }
+    }
+
+    /// The trigger's id, if it has one.
+    pub fn id(&self) -> Option<i32> {
+        each_typed!(self, typed => Some(typed.id), elem => elem.attr_i32("id").ok())
+    }
+
+    /// The trigger's pixel location in the `Level`, if it has one.
+    pub fn position(&self) -> Option<(i32, i32)> {
+        each_typed!(
+            self,
+            typed => Some((typed.x, typed.y)),
+            elem => Some((elem.attr_i32("x").ok()?, elem.attr_i32("y").ok()?))
+        )
+    }
+
+    /// The trigger's width and height in pixels, if it has them.
+    pub fn size(&self) -> Option<(i32, i32)> {
+        each_typed!(
+            self,
+            typed => Some((typed.width, typed.height)),
+            elem => Some((elem.attr_i32("width").ok()?, elem.attr_i32("height").ok()?))
+        )
+    }
+
+    /// The trigger's nodes. Nodes of a `Trigger::Other` that can't be parsed
+    /// are skipped.
+    pub fn nodes(&self) -> Vec<Node> {
+        each_typed!(
+            self,
+            typed => typed.nodes.clone(),
+            elem => elem
+                .get("node")
+                .filter_map(|node| Node::from_binel(BinElValue::Element(node.clone())).ok())
+                .collect()
+        )
+    }
+}
+
+impl BinElType for Trigger {
+    fn into_binel(self) -> BinElValue {
+        BinElValue::Element(self.into_elem())
+    }
+
+    fn from_binel(binel: BinElValue) -> Result<'static, Self> {
+        BinEl::from_binel(binel).map(|elem| Trigger::from_elem(&elem))
+    }
+
+    fn maybe_attr() -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn camera(camera_x: impl Into<BinElAttr>, camera_y: impl Into<BinElAttr>) -> BinEl {
+        BinEl::build("cameraOffsetTrigger")
+            .attr("id", 8)
+            .attr("x", 0)
+            .attr("y", 0)
+            .attr("width", 8)
+            .attr("height", 8)
+            .attr("cameraX", camera_x)
+            .attr("cameraY", camera_y)
+            .finish()
+    }
+
+    #[test]
+    fn number_fields() {
+        let trigger = Trigger::from_elem(&camera(1.5, -2.0));
+        match &trigger {
+            Trigger::CameraOffset(offset) => {
+                assert_eq!((offset.camera_x.get(), offset.camera_y.get()), (1.5, -2.0))
+            }
+            other => panic!("Expected camera offset trigger, got {:?}", other),
+        }
+        assert_eq!(trigger.into_elem(), camera(1.5, -2.0));
+
+        // Celeste's packer stores whole numbers as ints, and they are written
+        // back the way they were read
+        let trigger = Trigger::from_elem(&camera(0, 1.0));
+        match &trigger {
+            Trigger::CameraOffset(offset) => {
+                assert_eq!(offset.camera_x, Number::Int(0));
+                assert_eq!(offset.camera_y, Number::Float(1.0));
+            }
+            other => panic!("Expected camera offset trigger, got {:?}", other),
+        }
+        let elem = trigger.into_elem();
+        assert_eq!(elem.attributes.get("cameraX"), Some(&BinElAttr::Int(0)));
+        assert_eq!(elem.attributes.get("cameraY"), Some(&BinElAttr::Float(1.0)));
+    }
+
+    #[test]
+    fn nodes_and_order() {
+        // the size is read before the position, unlike the field order
+        let respawn = BinEl::build("changeRespawnTrigger")
+            .attr("width", 24)
+            .attr("height", 32)
+            .attr("x", 8)
+            .attr("y", 16)
+            .attr("id", 7)
+            .child(BinEl::build("node").attr("x", 12).attr("y", 40))
+            .child(BinEl::build("node").attr("x", 20).attr("y", 40))
+            .finish();
+
+        let trigger = Trigger::from_elem(&respawn);
+        match &trigger {
+            Trigger::ChangeRespawn(typed) => assert_eq!(
+                typed.nodes,
+                vec![Node { x: 12, y: 40 }, Node { x: 20, y: 40 }]
+            ),
+            other => panic!("Expected respawn trigger, got {:?}", other),
+        }
+        assert_eq!(trigger.size(), Some((24, 32)));
+
+        // equality ignores the order of attributes
+        let elem = trigger.into_elem();
+        assert_eq!(elem, respawn);
+        assert!(elem.attributes.keys().eq(respawn.attributes.keys()));
+
+        // nodes of a trigger that stays raw are still read
+        let mut extra = respawn;
+        extra.insert(BinEl::new("unknown"));
+        let trigger = Trigger::from_elem(&extra);
+        assert_eq!(trigger, Trigger::Other(extra));
+        assert_eq!(trigger.nodes().len(), 2);
+    }
+}
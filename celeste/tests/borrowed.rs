@@ -0,0 +1,25 @@
+use celeste::*;
+use std::borrow::Cow;
+
+#[test]
+fn borrowed_matches_owned() {
+    let map_bytes = &include_bytes!("empty.bin")[..];
+    let owned = binel::parser::take_file::<Error>(map_bytes).unwrap().1;
+    let borrowed = binel::parser::take_file_ref::<Error>(map_bytes).unwrap().1;
+
+    assert_eq!(borrowed.package, "test");
+    let level = borrowed
+        .root
+        .get("levels")
+        .next()
+        .unwrap()
+        .get("level")
+        .next()
+        .unwrap();
+    match level.attributes.get("name") {
+        Some(binel::BinElAttrRef::Text(Cow::Borrowed(name))) => assert_eq!(*name, "lvl_1"),
+        other => panic!("Didn't get borrowed text: {:?}", other),
+    }
+
+    assert_eq!(borrowed.into_owned(), owned);
+}
@@ -0,0 +1,57 @@
+use celeste::binel::*;
+use celeste::Error;
+use proptest::prelude::*;
+
+// Strings with long runs of Latin-1 characters, such as tile rows.
+fn latin1_runs() -> impl Strategy<Value = String> {
+    prop::collection::vec((0u8..=255, 1usize..600), 0..20).prop_map(|runs| {
+        runs.into_iter()
+            .map(|(byte, count)| char::from(byte).to_string().repeat(count))
+            .collect()
+    })
+}
+
+fn rle_bytes(string: &str) -> Vec<u8> {
+    let rle = writer::encode_rle_string(string).unwrap();
+    let mut buf = (rle.len() as i16).to_le_bytes().to_vec();
+    buf.extend(rle);
+    buf
+}
+
+proptest! {
+    #[test]
+    fn rle_roundtrip(string in latin1_runs()) {
+        let buf = rle_bytes(&string);
+        let (rest, decoded) = parser::take_rle_string::<Error>(&buf).unwrap();
+        prop_assert!(rest.is_empty());
+        prop_assert_eq!(&decoded, &string);
+        prop_assert_eq!(rle_bytes(&decoded), buf);
+    }
+
+    #[test]
+    fn rle_runs_fit_in_a_byte(string in latin1_runs()) {
+        let rle = writer::encode_rle_string(&string).unwrap();
+        prop_assert!(rle.chunks(2).all(|pair| pair[0] > 0));
+        prop_assert_eq!(
+            rle.chunks(2).map(|pair| pair[0] as usize).sum::<usize>(),
+            string.chars().count()
+        );
+    }
+
+    #[test]
+    fn rle_rejects_non_latin1(prefix in latin1_runs(), ch in prop::char::range('\u{100}', char::MAX)) {
+        prop_assert_eq!(writer::encode_rle_string(&format!("{}{}", prefix, ch)), None);
+    }
+
+    #[test]
+    fn tagged_str_roundtrip(string in any::<String>(), runs in latin1_runs(), in_lookup: bool) {
+        for val in &[string, runs] {
+            let lookup = if in_lookup { vec![val.clone()] } else { vec![] };
+            let mut buf = Vec::new();
+            writer::put_tagged_str(&mut buf, &lookup, val).unwrap();
+            let (rest, attr) = parser::take_elemattr::<Error>(&lookup)(&buf).unwrap();
+            prop_assert!(rest.is_empty());
+            prop_assert_eq!(attr, BinElAttr::Text(val.clone()));
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use celeste::*;
+use nom::multi::count;
+use nom::number::complete::le_i16;
+use std::io::Cursor;
+
+#[test]
+fn roundtrip_preserves_order() {
+    let map_bytes = &include_bytes!("empty.bin")[..];
+    let map_bin = binel::parser::take_file::<Error>(map_bytes).unwrap().1;
+
+    let (buf, _) = binel::parser::take_string::<Error>(map_bytes).unwrap();
+    let (buf, _) = binel::parser::take_string::<Error>(buf).unwrap();
+    let (buf, length) = le_i16::<Error>(buf).unwrap();
+    let (elem_bytes, lookup) =
+        count(binel::parser::take_string::<Error>, length as usize)(buf).unwrap();
+
+    let mut written = Cursor::new(Vec::new());
+    binel::writer::put_element(&mut written, &lookup, &map_bin.root).unwrap();
+    assert_eq!(&written.get_ref()[..], elem_bytes);
+
+    let mut file_buf = Cursor::new(Vec::new());
+    binel::writer::put_file(&mut file_buf, &map_bin).unwrap();
+    let reparsed = binel::parser::take_file::<Error>(&file_buf.get_ref()[..])
+        .unwrap()
+        .1;
+    assert_eq!(reparsed, map_bin);
+}
+
+#[test]
+fn lossless_roundtrip() {
+    let map_bytes = &include_bytes!("empty.bin")[..];
+    let map_bin = binel::parser::take_file_lossless::<Error>(map_bytes)
+        .unwrap()
+        .1;
+    assert!(map_bin.lookup.is_some());
+
+    // Tags don't affect equality.
+    let plain = binel::parser::take_file::<Error>(map_bytes).unwrap().1;
+    assert_eq!(map_bin.root, plain.root);
+
+    let mut file_buf = Cursor::new(Vec::new());
+    binel::writer::put_file(&mut file_buf, &map_bin).unwrap();
+    assert_eq!(&file_buf.get_ref()[..], map_bytes);
+}
+
+#[test]
+fn lossless_patch() {
+    let map_bytes = &include_bytes!("empty.bin")[..];
+    let mut map_bin = binel::parser::take_file_lossless::<Error>(map_bytes)
+        .unwrap()
+        .1;
+
+    let level = map_bin
+        .root
+        .get_mut("levels")
+        .next()
+        .unwrap()
+        .get_mut("level")
+        .next()
+        .unwrap();
+    level
+        .attributes
+        .insert("width".to_string(), binel::BinElAttr::Int(328));
+
+    let mut file_buf = Cursor::new(Vec::new());
+    binel::writer::put_file(&mut file_buf, &map_bin).unwrap();
+    let written = file_buf.get_ref();
+
+    assert_eq!(written.len(), map_bytes.len());
+    let changed = written
+        .iter()
+        .zip(map_bytes)
+        .filter(|(a, b)| a != b)
+        .count();
+    assert_eq!(changed, 1);
+}
+
+#[test]
+fn roundtrip_nested_fixture() {
+    let map_bytes = &include_bytes!("rooms.bin")[..];
+    let map_bin = binel::parser::take_file::<Error>(map_bytes).unwrap().1;
+    assert_eq!(map_bin.package, "Fixture");
+
+    let names = |elem: &binel::BinEl| -> Vec<String> {
+        elem.children().map(|child| child.name.clone()).collect()
+    };
+    assert_eq!(names(&map_bin.root), ["levels", "Style", "Filler"]);
+
+    let levels = map_bin.root.get("levels").next().unwrap();
+    let level_names: Vec<_> = levels
+        .get("level")
+        .map(|level| level.attr_str("name").unwrap())
+        .collect();
+    assert_eq!(level_names, ["b-01", "a-00"]);
+
+    let level = levels.get("level").nth(1).unwrap();
+    let keys: Vec<_> = level.attributes.keys().map(String::as_str).collect();
+    assert_eq!(
+        keys,
+        ["name", "x", "y", "width", "height", "dark", "music", "c"]
+    );
+    assert_eq!(level.attr_i32("x").unwrap(), -320);
+    assert_eq!(names(level), ["solids", "entities", "bg", "fgdecals"]);
+
+    let entities = level.get("entities").next().unwrap();
+    assert_eq!(names(entities), ["player", "refill", "zipMover", "refill"]);
+    let ids: Vec<_> = entities
+        .children()
+        .map(|entity| entity.attr_i32("id").unwrap())
+        .collect();
+    assert_eq!(ids, [1, 3, 2, 4]);
+    assert_eq!(
+        entities.get("zipMover").next().unwrap().get("node").count(),
+        1
+    );
+
+    // Written with the file's own lookup, elements come out byte for byte.
+    let (buf, _) = binel::parser::take_string::<Error>(map_bytes).unwrap();
+    let (buf, _) = binel::parser::take_string::<Error>(buf).unwrap();
+    let (buf, length) = le_i16::<Error>(buf).unwrap();
+    let (elem_bytes, lookup) =
+        count(binel::parser::take_string::<Error>, length as usize)(buf).unwrap();
+
+    let mut written = Cursor::new(Vec::new());
+    binel::writer::put_element(&mut written, &lookup, &map_bin.root).unwrap();
+    assert_eq!(&written.get_ref()[..], elem_bytes);
+
+    let mut file_buf = Cursor::new(Vec::new());
+    binel::writer::put_file(&mut file_buf, &map_bin).unwrap();
+    let reparsed = binel::parser::take_file::<Error>(&file_buf.get_ref()[..])
+        .unwrap()
+        .1;
+    assert_eq!(reparsed, map_bin);
+}
@@ -5,8 +5,8 @@ use std::env;
 use std::fmt::Write;
 
 fn get_url(url: &str) -> String {
-    if url.starts_with("file://") {
-        std::fs::read_to_string(&url["file://".len()..]).unwrap()
+    if let Some(path) = url.strip_prefix("file://") {
+        std::fs::read_to_string(path).unwrap()
     } else {
         attohttpc::get(url).send().unwrap().text().unwrap()
     }
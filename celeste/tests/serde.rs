@@ -0,0 +1,78 @@
+use celeste::binel::{serialize::*, *};
+use celeste::*;
+use std::io::Cursor;
+
+#[test]
+fn json_roundtrip() {
+    let map_bytes = include_bytes!("empty.bin");
+    let map_bin = parser::take_file_lossless::<Error>(map_bytes).unwrap().1;
+
+    let json = serde_json::to_string(&map_bin).unwrap();
+    let from_json: BinFile = serde_json::from_str(&json).unwrap();
+    assert_eq!(from_json, map_bin);
+
+    let mut buf = Cursor::new(Vec::new());
+    writer::put_file(&mut buf, &from_json).unwrap();
+    assert_eq!(&buf.get_ref()[..], &map_bytes[..]);
+}
+
+#[test]
+fn json_attr_types() {
+    let attrs = vec![
+        BinElAttr::Bool(true),
+        BinElAttr::Int(1),
+        BinElAttr::Float(1.0),
+        BinElAttr::Text("1".to_string()),
+    ];
+    let json = serde_json::to_string(&attrs).unwrap();
+    assert_eq!(
+        json,
+        r#"[{"Bool":true},{"Int":1},{"Float":1.0},{"Text":"1"}]"#
+    );
+    let from_json: Vec<BinElAttr> = serde_json::from_str(&json).unwrap();
+    assert_eq!(from_json, attrs);
+}
+
+#[test]
+fn json_map_roundtrip() {
+    for map_bytes in &[
+        &include_bytes!("empty.bin")[..],
+        include_bytes!("rooms.bin"),
+    ] {
+        let map_bin = parser::take_file_lossless::<Error>(map_bytes).unwrap().1;
+        let map = maps::Map::from_binel(BinElValue::Element(map_bin.root)).unwrap();
+
+        let json = serde_json::to_string(&map).unwrap();
+        let from_json: maps::Map = serde_json::from_str(&json).unwrap();
+        assert_eq!(from_json, map);
+
+        // the lookup table keeps the strings in the same order
+        let root = match from_json.into_binel() {
+            BinElValue::Element(root) => root,
+            _ => panic!("Didn't get element!"),
+        };
+        let file = BinFile { root, ..map_bin };
+        let mut buf = Cursor::new(Vec::new());
+        writer::put_file(&mut buf, &file).unwrap();
+        assert_eq!(&buf.get_ref()[..], &map_bytes[..]);
+    }
+}
+
+#[test]
+fn json_diff_roundtrip() {
+    let map_bytes = include_bytes!("empty.bin");
+    let old = parser::take_file::<Error>(map_bytes).unwrap().1.root;
+    let mut new = old.clone();
+    new.attributes
+        .insert("dark".to_string(), BinElAttr::Bool(true));
+    new.insert(BinEl::new("Filler"));
+
+    let patch = diff::diff(&old, &new);
+    let json = serde_json::to_string(&patch).unwrap();
+    let from_json: diff::Diff = serde_json::from_str(&json).unwrap();
+    assert_eq!(from_json, patch);
+
+    let mut patched = old.clone();
+    from_json.apply(&mut patched).unwrap();
+    assert_eq!(patched, new);
+}
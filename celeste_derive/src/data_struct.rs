@@ -24,12 +24,17 @@ pub(crate) fn binel_type_struct(input: DeriveInput, name: String) -> proc_macro:
     let mut d_vec_names = Vec::new();
     let mut d_req_idents = Vec::new();
     let mut d_req_err_names = Vec::new();
+    let mut d_req_types = Vec::new();
     let mut d_opt_idents = Vec::new();
     let mut d_skip_idents = Vec::new();
+    let mut other_ident = None;
+    let mut order_ident = None;
+    let mut child_order_ident = None;
 
     for field in &body.fields {
         let mut skip = false;
         let mut is_vec = false;
+        let mut keep = None;
         let ident = match field.ident {
             Some(ref ident) => ident.clone(),
             None => panic!("Your struct is missing a field identity!"),
@@ -45,6 +50,12 @@ pub(crate) fn binel_type_struct(input: DeriveInput, name: String) -> proc_macro:
                     if word == "celeste_skip" {
                         skip = true;
                     }
+                    if word == "celeste_other"
+                        || word == "celeste_order"
+                        || word == "celeste_child_order"
+                    {
+                        keep = Some(word.clone());
+                    }
                     assert_ne!(word, "celeste_name", "celeste_name must have a value!");
                 }
                 Ok(Meta::List(list)) => {
@@ -76,7 +87,13 @@ pub(crate) fn binel_type_struct(input: DeriveInput, name: String) -> proc_macro:
 
         let mut is_opt = false;
 
-        match &field.ty {
+        // types passed through macro_rules are wrapped in an invisible group
+        let mut ty = &field.ty;
+        while let Type::Group(group) = ty {
+            ty = &group.elem;
+        }
+
+        match ty {
             Type::Path(path) => {
                 let path = &path.path;
 
@@ -121,7 +138,21 @@ pub(crate) fn binel_type_struct(input: DeriveInput, name: String) -> proc_macro:
             }
         }
 
-        if skip {
+        let keep_ident = match keep.as_deref() {
+            Some("celeste_other") => Some(&mut other_ident),
+            Some("celeste_order") => Some(&mut order_ident),
+            Some("celeste_child_order") => Some(&mut child_order_ident),
+            _ => None,
+        };
+
+        if let Some(keep_ident) = keep_ident {
+            assert!(
+                keep_ident.is_none(),
+                "{} can only be used once!",
+                keep.unwrap()
+            );
+            *keep_ident = Some(ident);
+        } else if skip {
             d_skip_idents.push(ident);
         } else if !is_vec {
             if is_opt {
@@ -129,6 +160,7 @@ pub(crate) fn binel_type_struct(input: DeriveInput, name: String) -> proc_macro:
             } else {
                 d_req_idents.push(ident.clone());
                 d_req_err_names.push(name.clone());
+                d_req_types.push(field.ty.clone());
             }
 
             s_idents.push(ident);
@@ -146,33 +178,33 @@ pub(crate) fn binel_type_struct(input: DeriveInput, name: String) -> proc_macro:
     // prepend
     let s_fields: Vec<Ident> = s_idents
         .iter()
-        .map(|e| Ident::new(format!("field_{}", e.to_string()).as_str(), e.span()))
+        .map(|e| Ident::new(format!("field_{}", e).as_str(), e.span()))
         .collect();
     let s_vec_fields: Vec<Ident> = s_vec_idents
         .iter()
-        .map(|e| Ident::new(format!("field_{}", e.to_string()).as_str(), e.span()))
+        .map(|e| Ident::new(format!("field_{}", e).as_str(), e.span()))
         .collect();
 
     let d_req_err_idents: Vec<Ident> = d_req_idents
         .iter()
-        .map(|e| Ident::new(format!("_error_{}", e.to_string()).as_str(), e.span()))
+        .map(|e| Ident::new(format!("_error_{}", e).as_str(), e.span()))
         .collect();
     let d_err_idents: Vec<Ident> = s_idents
         .iter()
-        .map(|e| Ident::new(format!("_error_{}", e.to_string()).as_str(), e.span()))
+        .map(|e| Ident::new(format!("_error_{}", e).as_str(), e.span()))
         .collect();
     let d_req_idents: Vec<Ident> = d_req_idents
         .iter()
-        .map(|e| Ident::new(format!("field_{}", e.to_string()).as_str(), e.span()))
+        .map(|e| Ident::new(format!("field_{}", e).as_str(), e.span()))
         .collect();
     let d_opt_idents: Vec<Ident> = d_opt_idents
         .iter()
-        .map(|e| Ident::new(format!("field_{}", e.to_string()).as_str(), e.span()))
+        .map(|e| Ident::new(format!("field_{}", e).as_str(), e.span()))
         .collect();
 
     // errors
-    let d_err_check_name = name.clone();
-    let d_err_elem_name = name.clone();
+    let d_err_check_name = format!("element `{}`", name);
+    let d_err_elem_name = d_err_check_name.clone();
     let d_err_names = s_names.iter();
     let d_req_missing_names = d_req_err_names.iter();
     let d_req_err_names = d_req_err_names.iter();
@@ -180,6 +212,7 @@ pub(crate) fn binel_type_struct(input: DeriveInput, name: String) -> proc_macro:
     let d_is_attr_err_idents = d_err_idents.iter();
     let d_is_elem_err_idents = d_err_idents.iter();
     let d_req_err_idents = d_req_err_idents.iter();
+    let d_req_types = d_req_types.iter();
 
     // disable mutability and make into iterators
     let d_skip_idents = d_skip_idents.iter();
@@ -213,7 +246,46 @@ pub(crate) fn binel_type_struct(input: DeriveInput, name: String) -> proc_macro:
     let d_field_inits = d_idents
         .zip(d_fields)
         .map(|(ident, field)| quote! { #ident: #field });
-    let d_inits = d_field_inits.chain(d_skip_inits);
+
+    // the attributes left over, and the order of everything that was read
+    let field = |ident: &Ident| Ident::new(format!("field_{}", ident).as_str(), ident.span());
+    let d_order = order_ident.as_ref().map(|ident| {
+        let field = field(ident);
+        quote! { let #field: Vec<String> = binel.attributes.keys().cloned().collect(); }
+    });
+    let d_child_order = child_order_ident.as_ref().map(|ident| {
+        let field = field(ident);
+        quote! { let #field: Vec<String> = binel.children().map(|child| child.name.clone()).collect(); }
+    });
+    let d_other = other_ident.as_ref().map(|ident| {
+        let field = field(ident);
+        quote! { let #field = std::mem::take(&mut binel.attributes); }
+    });
+    let d_keep_inits = other_ident
+        .iter()
+        .chain(order_ident.iter())
+        .chain(child_order_ident.iter())
+        .map(|ident| {
+            let field = field(ident);
+            quote! { #ident: #field }
+        });
+    let d_inits = d_field_inits.chain(d_skip_inits).chain(d_keep_inits);
+
+    let s_other = other_ident.as_ref().map(|ident| {
+        quote! { binel.attributes.extend(self.#ident); }
+    });
+    let s_order = order_ident.as_ref().map(|ident| {
+        quote! { serialize::reorder_attributes(&mut binel.attributes, &self.#ident); }
+    });
+    let s_child_order = child_order_ident.as_ref().map(|ident| {
+        quote! {
+            let mut children: Vec<BinEl> = binel.drain().collect();
+            serialize::reorder_children(&mut children, &self.#ident);
+            for child in children {
+                binel.insert(child);
+            }
+        }
+    });
 
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
@@ -225,19 +297,25 @@ pub(crate) fn binel_type_struct(input: DeriveInput, name: String) -> proc_macro:
 
                 let mut binel = match binel {
                     serialize::BinElValue::Element(elem) => elem,
-                    _ => return Err(Error::from_name(#d_err_elem_name)),
+                    other => return Err(Error::deserialize(#d_err_elem_name, other.describe())),
                 };
 
                 if binel.name != #check_name {
-                    return Err(Error::wrong_name(#d_err_check_name, binel.name.clone()));
+                    return Err(Error::deserialize(
+                        #d_err_check_name,
+                        format!("element `{}`", binel.name),
+                    ));
                 }
 
+                #d_order
+                #d_child_order
+
                 #(
                     let mut #d_idents_none = None;
                     let mut #d_maybe_err_idents = None;
 
                     if <#d_types_maybe_attr as serialize::BinElType>::maybe_attr() {
-                        #d_idents_attr = match binel.attributes.remove(#d_names) {
+                        #d_idents_attr = match binel.attributes.shift_remove(#d_names) {
                             Some(attr) => match <#d_types_attr as serialize::BinElType>::from_binel(serialize::BinElValue::Attribute(attr)) {
                                 Ok(val) => Some(val),
                                 Err(err) => {#d_is_attr_err_idents = Some(err); None}
@@ -260,7 +338,10 @@ pub(crate) fn binel_type_struct(input: DeriveInput, name: String) -> proc_macro:
                                 ::from_binel(serialize::BinElValue::Element(child.clone()));
 
                             #d_idents_checked = match (#d_idents_check, maybe) {
-                                (Some(_), Ok(_)) => return Err(Error::from_name(#d_err_names)),
+                                (Some(_), Ok(_)) => return Err(
+                                    Error::deserialize("a single value", "more than one".to_string())
+                                        .in_path(#d_err_names)
+                                ),
                                 (Some(attr), Err(_)) => Some(attr),
                                 (None, Ok(child)) => Some(child),
                                 (None, Err(err)) => {#d_is_elem_err_idents = Some(err); None}
@@ -285,11 +366,19 @@ pub(crate) fn binel_type_struct(input: DeriveInput, name: String) -> proc_macro:
                     )*
                 }
 
+                #d_other
+
                 #(
                     let #d_req_idents = match (#d_req_idents_check, #d_req_err_idents) {
                         (Some(val), _) => val,
-                        (None, None) => return Err(Error::from_name(#d_req_missing_names)),
-                        (None, Some(err)) => return Err(Error::from_name(#d_req_err_names)),
+                        (None, None) => return Err(
+                            Error::deserialize(
+                                serialize::describe_type::<#d_req_types>(stringify!(#d_req_types)),
+                                "nothing".to_string(),
+                            )
+                            .in_path(#d_req_missing_names)
+                        ),
+                        (None, Some(err)) => return Err(err.in_path(#d_req_err_names)),
                     };
                 )*
                 #(
@@ -333,6 +422,10 @@ pub(crate) fn binel_type_struct(input: DeriveInput, name: String) -> proc_macro:
                     }
                 )*
 
+                #s_other
+                #s_order
+                #s_child_order
+
                 serialize::BinElValue::Element(binel)
             }
 
@@ -8,7 +8,17 @@ use syn::*;
 mod data_struct;
 mod newtype;
 
-#[proc_macro_derive(BinElType, attributes(celeste_child_vec, celeste_name, celeste_skip))]
+#[proc_macro_derive(
+    BinElType,
+    attributes(
+        celeste_child_vec,
+        celeste_name,
+        celeste_skip,
+        celeste_other,
+        celeste_order,
+        celeste_child_order
+    )
+)]
 pub fn binel_type(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
@@ -18,7 +28,7 @@ pub fn binel_type(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 
     let is_newtype = match &input.data {
         Data::Struct(data) => {
-            data.fields.iter().count() == 1 && data.fields.iter().all(|field| field.ident == None)
+            data.fields.iter().count() == 1 && data.fields.iter().all(|field| field.ident.is_none())
         }
         _ => false,
     };
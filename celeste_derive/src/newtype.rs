@@ -5,7 +5,7 @@ pub(crate) fn binel_type_newtype(input: DeriveInput, name: String) -> proc_macro
     let ident = input.ident;
 
     let check_name = name.clone();
-    let err_name = name.clone();
+    let err_name = format!("element `{}`", name);
 
     let ty = match &input.data {
         Data::Struct(data) => &data.fields.iter().next().unwrap().ty,
@@ -25,7 +25,10 @@ pub(crate) fn binel_type_newtype(input: DeriveInput, name: String) -> proc_macro
 
                 if let serialize::BinElValue::Element(elem) = &binel {
                     if elem.name != #check_name {
-                        return Err(Error::wrong_name(#err_name, elem.name.clone()));
+                        return Err(Error::deserialize(
+                            #err_name,
+                            format!("element `{}`", elem.name),
+                        ));
                     }
                 }
 
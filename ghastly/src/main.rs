@@ -49,7 +49,7 @@ pub async fn server(addr: impl ToSocketAddrs + Clone) -> Result<'static, ()> {
         async move {
             let mut data = [0u8; 1024];
             while let Ok((read, addr)) = udp.recv_from(&mut data).await {
-                let channel = udp_map.lock().await.get(&addr.ip()).map(Clone::clone);
+                let channel = udp_map.lock().await.get(&addr.ip()).cloned();
                 if let Some(channel) = channel {
                     let _ = channel.unbounded_send((addr, Vec::from(&data[..read])));
                 }
@@ -311,7 +311,7 @@ pub async fn handle(
                 res @ Err(nom::Err::Incomplete(_)) => {
                     std::mem::drop(res);
 
-                    buf.extend(std::iter::repeat(0).take(start + 128 - buf.len()));
+                    buf.extend(std::iter::repeat_n(0, start + 128 - buf.len()));
                     eprintln!("reading");
                     let read = read.read(&mut buf[start..]).await?;
                     if read != 0 {
@@ -387,12 +387,10 @@ pub async fn handle(
         while let Some((new_state, frame)) = udp_broadcast_rx.receive(state).await {
             state = new_state;
 
-            let addr;
-
-            match *udp_broadcast_addr.lock().await {
-                Some(a) => addr = a,
+            let addr = match *udp_broadcast_addr.lock().await {
+                Some(a) => a,
                 None => continue,
-            }
+            };
 
             let mut sent = 0;
 